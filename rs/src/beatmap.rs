@@ -0,0 +1,175 @@
+use crate::density::TempoMap;
+use serde_json::Value;
+
+/// The objects of a single difficulty file, independent of its schema version.
+pub struct Beatmap {
+    /// Sorted by beat. Includes bombs and both ends of arcs, which all count towards `Notes`.
+    pub notes: Vec<Note>,
+    pub obstacles: Vec<Obstacle>,
+    /// Beats of every chain link after the head, which each score and count towards combo.
+    pub chain_links: Vec<f64>,
+    pub tempo_map: TempoMap,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum NoteKind {
+    Color,
+    Bomb,
+    ArcHead,
+    ArcTail,
+    ChainHead,
+}
+
+pub struct Note {
+    pub beat: f64,
+    pub kind: NoteKind,
+    pub x: i64,        // 0-3 left to right, except with Mapping Extensions
+    pub y: i64,        // 0-2 bottom to top, except with Mapping Extensions
+    pub color: u8,     // 0 is left/red and 1 is right/blue, meaningless for bombs
+    pub direction: u8, // 0-7 are up, down, left, right, up-left, up-right, down-left, down-right; 8 is any
+}
+
+pub const DIRECTION_ANY: u8 = 8;
+
+// keys for (beat, x, y, color, direction)
+type NoteKeys = [&'static str; 5];
+const V2_NOTE: NoteKeys = [
+    "_time",
+    "_lineIndex",
+    "_lineLayer",
+    "_type",
+    "_cutDirection",
+];
+const V2_ARC_HEAD: NoteKeys = [
+    "_time",
+    "_headLineIndex",
+    "_headLineLayer",
+    "_colorType",
+    "_headCutDirection",
+];
+const V2_ARC_TAIL: NoteKeys = [
+    "_tailTime",
+    "_tailLineIndex",
+    "_tailLineLayer",
+    "_colorType",
+    "_tailCutDirection",
+];
+const V3_NOTE: NoteKeys = ["b", "x", "y", "c", "d"];
+const V3_ARC_TAIL: NoteKeys = ["tb", "tx", "ty", "c", "tc"];
+
+pub struct Obstacle {
+    pub beat: f64,
+    pub duration: f64, // beats
+}
+
+impl Beatmap {
+    /// Reads the objects of a v2 or v3 difficulty file, or describes why it can't be read, e.g. an
+    /// unknown version or a note without a time.
+    pub fn parse(difficulty_file: &Value, bpm: f64) -> Result<Beatmap, String> {
+        let version = difficulty_file["version"].as_str().unwrap_or("2.0.0");
+        let empty = Vec::new();
+        let mut notes = Vec::<Note>::new();
+        let mut chain_links = Vec::new();
+        let obstacles = match version.chars().next() {
+            Some('3') => {
+                let array = |key: &str| difficulty_file[key].as_array().unwrap_or(&empty);
+                for (key, kind) in [
+                    ("colorNotes", NoteKind::Color),
+                    ("bombNotes", NoteKind::Bomb),
+                    ("burstSliders", NoteKind::ChainHead),
+                ] {
+                    for n in array(key) {
+                        notes.push(Note::new(n, V3_NOTE, kind)?);
+                    }
+                }
+                for n in array("sliders") {
+                    notes.push(Note::new(n, V3_NOTE, NoteKind::ArcHead)?);
+                    notes.push(Note::new(n, V3_ARC_TAIL, NoteKind::ArcTail)?);
+                }
+                for chain in array("burstSliders") {
+                    let head_beat = number(chain, "b")?;
+                    let tail_beat = number(chain, "tb")?;
+                    // the slice count includes the head
+                    let links = chain["sc"].as_u64().unwrap_or(1).max(1) - 1;
+                    chain_links
+                        .extend((1..=links).map(|i| {
+                            head_beat + (tail_beat - head_beat) * i as f64 / links as f64
+                        }));
+                }
+                array("obstacles")
+                    .iter()
+                    .map(|o| Obstacle::new(o, "b", "d"))
+                    .collect::<Result<Vec<_>, _>>()?
+            }
+            Some('2') => {
+                let v2_notes = difficulty_file["_notes"]
+                    .as_array()
+                    .ok_or("no _notes array")?;
+                for n in v2_notes {
+                    let kind = if n["_type"].as_u64() == Some(3) {
+                        NoteKind::Bomb
+                    } else {
+                        NoteKind::Color
+                    };
+                    notes.push(Note::new(n, V2_NOTE, kind)?);
+                }
+                // arcs were added in 2.6.0
+                for n in difficulty_file["_sliders"].as_array().unwrap_or(&empty) {
+                    notes.push(Note::new(n, V2_ARC_HEAD, NoteKind::ArcHead)?);
+                    notes.push(Note::new(n, V2_ARC_TAIL, NoteKind::ArcTail)?);
+                }
+                difficulty_file["_obstacles"]
+                    .as_array()
+                    .unwrap_or(&empty)
+                    .iter()
+                    .map(|o| Obstacle::new(o, "_time", "_duration"))
+                    .collect::<Result<Vec<_>, _>>()?
+            }
+            _ => return Err(format!("unrecognized schema version {}", version)),
+        };
+        notes.sort_by(|a, b| a.beat.partial_cmp(&b.beat).unwrap());
+        Ok(Beatmap {
+            notes,
+            obstacles,
+            chain_links,
+            tempo_map: TempoMap::from_difficulty(bpm, difficulty_file),
+        })
+    }
+}
+
+/// A required number property of a beatmap object.
+fn number(object: &Value, key: &str) -> Result<f64, String> {
+    object[key]
+        .as_f64()
+        .ok_or_else(|| format!("object without a {} number: {}", key, object))
+}
+
+impl Note {
+    fn new(n: &Value, keys: NoteKeys, kind: NoteKind) -> Result<Note, String> {
+        Ok(Note {
+            beat: number(n, keys[0])?,
+            kind,
+            x: n[keys[1]].as_i64().unwrap_or(0),
+            y: n[keys[2]].as_i64().unwrap_or(0),
+            color: n[keys[3]].as_u64().unwrap_or(0) as u8,
+            direction: n[keys[4]]
+                .as_u64()
+                .map_or(DIRECTION_ANY, |d| d.min(DIRECTION_ANY as u64) as u8),
+        })
+    }
+
+    /// Whether this note is cut by a saber swing, rather than being a bomb or part of an arc or chain.
+    /// Chain heads are excluded because the head is also a color note.
+    pub fn is_swing(&self) -> bool {
+        self.kind == NoteKind::Color
+    }
+}
+
+impl Obstacle {
+    fn new(o: &Value, beat_key: &str, duration_key: &str) -> Result<Obstacle, String> {
+        Ok(Obstacle {
+            beat: number(o, beat_key)?,
+            duration: o[duration_key].as_f64().unwrap_or(0.0),
+        })
+    }
+}
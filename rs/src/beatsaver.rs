@@ -0,0 +1,102 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Song details of one version of a map from a BeatSaver metadata dump.
+pub struct DumpLevel {
+    pub song: String,
+    pub artist: String,
+    pub mapper: String,
+    pub bpm: f64,
+    pub duration: f64, // seconds of audio
+    pub difficulties: Vec<DumpDifficulty>,
+}
+
+pub struct DumpDifficulty {
+    pub characteristic: String,
+    /// Difficulty name as used in the output, e.g. `Expert+`.
+    pub difficulty: String,
+    pub notes: Option<u32>,
+    pub nps: Option<f64>,
+    pub max_score: Option<u32>,
+}
+
+/// Loads a BeatSaver metadata dump keyed by uppercase level hash.
+///
+/// Accepts a JSON array (or an object with a `docs` array) of either BeatSaver API map objects,
+/// where every entry in `versions` is keyed by its `hash`, or scraped entries with `Hash`,
+/// `SongName`, `SongAuthorName`, `LevelAuthorName`, `Bpm` and `Duration`.
+pub fn load(path: &Path) -> HashMap<String, DumpLevel> {
+    let dump: Value = serde_json::from_reader(fs::File::open(path).unwrap())
+        .unwrap_or_else(|e| panic!("BeatSaver dump at {} is not JSON: {}", path.display(), e));
+    let maps = match &dump {
+        Value::Array(maps) => maps,
+        _ => dump["docs"].as_array().unwrap_or_else(|| {
+            panic!(
+                "BeatSaver dump at {} is not an array of maps",
+                path.display()
+            )
+        }),
+    };
+    let mut levels = HashMap::new();
+    for map in maps {
+        if let Some(hash) = map["Hash"].as_str() {
+            levels.insert(
+                hash.to_ascii_uppercase(),
+                DumpLevel {
+                    song: map["SongName"].as_str().unwrap_or("").to_owned(),
+                    artist: map["SongAuthorName"].as_str().unwrap_or("").to_owned(),
+                    mapper: map["LevelAuthorName"].as_str().unwrap_or("").to_owned(),
+                    bpm: map["Bpm"].as_f64().unwrap_or(0.0),
+                    duration: map["Duration"].as_f64().unwrap_or(0.0),
+                    difficulties: Vec::new(),
+                },
+            );
+            continue;
+        }
+        let metadata = &map["metadata"];
+        for version in map["versions"].as_array().into_iter().flatten() {
+            let hash = match version["hash"].as_str() {
+                Some(h) => h,
+                None => continue,
+            };
+            levels.insert(
+                hash.to_ascii_uppercase(),
+                DumpLevel {
+                    song: metadata["songName"].as_str().unwrap_or("").to_owned(),
+                    artist: metadata["songAuthorName"].as_str().unwrap_or("").to_owned(),
+                    mapper: metadata["levelAuthorName"]
+                        .as_str()
+                        .unwrap_or("")
+                        .to_owned(),
+                    bpm: metadata["bpm"].as_f64().unwrap_or(0.0),
+                    duration: metadata["duration"].as_f64().unwrap_or(0.0),
+                    difficulties: version["diffs"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .map(|d| DumpDifficulty {
+                            characteristic: d["characteristic"].as_str().unwrap_or("").to_owned(),
+                            difficulty: match d["difficulty"].as_str().unwrap_or("") {
+                                "ExpertPlus" => "Expert+".to_owned(),
+                                name => name.to_owned(),
+                            },
+                            notes: d["notes"].as_u64().map(|n| n as u32),
+                            nps: d["nps"].as_f64(),
+                            max_score: d["maxScore"].as_u64().map(|s| s as u32),
+                        })
+                        .collect(),
+                },
+            );
+        }
+    }
+    levels
+}
+
+/// The hash part of a custom level ID, uppercased to match the dump keys.
+pub fn level_hash(level_id: &str) -> Option<String> {
+    level_id
+        .strip_prefix("custom_level_")
+        .map(|h| h.to_ascii_uppercase())
+}
@@ -0,0 +1,51 @@
+use serde::Serialize;
+use serde_json::Value;
+
+/// Reads a `_customData` property, given its name without the underscore.
+pub fn get<'a>(object: &'a Value, key: &str) -> &'a Value {
+    &object["_customData"][format!("_{}", key)]
+}
+
+/// Reads a list of strings such as `_requirements` or `_warnings`.
+pub fn string_list(object: &Value, key: &str) -> Vec<String> {
+    get(object, key)
+        .as_array()
+        .map(|list| {
+            list.iter()
+                .filter_map(|s| s.as_str().map(|s| s.to_owned()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Reads a color such as `_colorLeft` as `#RRGGBB`.
+pub fn color(object: &Value, key: &str) -> Option<String> {
+    let color = get(object, key);
+    let channel = |c: &str| Some((color[c].as_f64()?.clamp(0.0, 1.0) * 255.0).round() as u8);
+    Some(format!(
+        "#{:02X}{:02X}{:02X}",
+        channel("r")?,
+        channel("g")?,
+        channel("b")?
+    ))
+}
+
+#[derive(Serialize, Clone)]
+pub struct Contributor {
+    pub role: String,
+    pub name: String,
+}
+
+pub fn contributors(level_info_src: &Value) -> Vec<Contributor> {
+    get(level_info_src, "contributors")
+        .as_array()
+        .map(|list| {
+            list.iter()
+                .map(|c| Contributor {
+                    role: c["_role"].as_str().unwrap_or("").to_owned(),
+                    name: c["_name"].as_str().unwrap_or("").to_owned(),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
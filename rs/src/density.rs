@@ -0,0 +1,180 @@
+use serde::Serialize;
+use serde_json::Value;
+
+/// Converts beatmap beats to real seconds, following any BPM changes in the beatmap.
+pub struct TempoMap {
+    // (start beat, seconds at start beat, bpm), sorted by start beat
+    segments: Vec<(f64, f64, f64)>,
+}
+
+impl TempoMap {
+    /// `changes` are (beat, new bpm) pairs; the song starts at `initial_bpm` until the first change.
+    pub fn new(initial_bpm: f64, mut changes: Vec<(f64, f64)>) -> TempoMap {
+        changes.retain(|c| c.1 > 0.0);
+        changes.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        let mut segments = vec![(0.0, 0.0, initial_bpm)];
+        for (beat, bpm) in changes {
+            let last = *segments.last().unwrap();
+            if beat <= last.0 {
+                // a change at (or before) the start of the current segment replaces it
+                segments.last_mut().unwrap().2 = bpm;
+                continue;
+            }
+            let seconds = last.1 + (beat - last.0) / last.2 * 60.0;
+            segments.push((beat, seconds, bpm));
+        }
+        TempoMap { segments }
+    }
+
+    /// Reads the BPM changes out of a difficulty file of any supported schema version.
+    pub fn from_difficulty(initial_bpm: f64, difficulty_file: &Value) -> TempoMap {
+        let mut changes = Vec::new();
+        // v3
+        if let Some(bpm_events) = difficulty_file["bpmEvents"].as_array() {
+            changes.extend(
+                bpm_events
+                    .iter()
+                    .filter_map(|e| Some((e["b"].as_f64().unwrap_or(0.0), e["m"].as_f64()?))),
+            );
+        }
+        // v2 official BPM change events
+        if let Some(events) = difficulty_file["_events"].as_array() {
+            changes.extend(
+                events
+                    .iter()
+                    .filter(|e| e["_type"].as_u64() == Some(100))
+                    .filter_map(|e| Some((e["_time"].as_f64()?, e["_floatValue"].as_f64()?))),
+            );
+        }
+        // v2 mapping tool BPM changes
+        for key in ["_BPMChanges", "_bpmChanges"] {
+            if let Some(bpm_changes) = difficulty_file["_customData"][key].as_array() {
+                changes.extend(bpm_changes.iter().filter_map(|e| {
+                    Some((
+                        e["_time"].as_f64()?,
+                        e["_BPM"].as_f64().or_else(|| e["_bpm"].as_f64())?,
+                    ))
+                }));
+            }
+        }
+        TempoMap::new(initial_bpm, changes)
+    }
+
+    pub fn seconds(&self, beat: f64) -> f64 {
+        let idx = self.segments.partition_point(|s| s.0 <= beat).max(1) - 1;
+        let (start_beat, start_seconds, bpm) = self.segments[idx];
+        start_seconds + (beat - start_beat) / bpm * 60.0
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct PeakDensity {
+    pub window: f64, // seconds
+    pub nps: f64,
+}
+
+/// Highest average notes per second over any `window` seconds, rounded to two decimals.
+/// `times` must be sorted, in seconds.
+pub fn peak_density(times: &[f64], window: f64) -> f64 {
+    let mut highest_so_far = 0;
+    let mut end_idx = 0;
+    for (start_idx, start_time) in times.iter().enumerate() {
+        let limit = start_time + window;
+        while end_idx < times.len() && times[end_idx] <= limit {
+            end_idx += 1;
+        }
+        highest_so_far = highest_so_far.max(end_idx - start_idx);
+    }
+    round2(highest_so_far as f64 / window)
+}
+
+pub fn round2(x: f64) -> f64 {
+    (x * 100.0).round() / 100.0
+}
+
+/// Parses a comma-separated list of window lengths in seconds, e.g. `1,4,10,30`. Each has to be a
+/// positive number and appear once, since it becomes a column.
+pub fn parse_windows(s: &str) -> Vec<f64> {
+    let mut windows = Vec::new();
+    for w in s.split(',') {
+        let window = w
+            .trim()
+            .parse::<f64>()
+            .unwrap_or_else(|_| panic!("Density windows must be numbers, got {}", w));
+        if !window.is_finite() || window <= 0.0 {
+            panic!("Density windows must be positive, got {}", w);
+        }
+        if windows.contains(&window) {
+            panic!("Density window {} is given twice", w);
+        }
+        windows.push(window);
+    }
+    windows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn windows() {
+        assert_eq!(parse_windows("1,4,10,30"), [1.0, 4.0, 10.0, 30.0]);
+        assert_eq!(parse_windows(" 1.5 , 10"), [1.5, 10.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Density windows must be numbers, got ten")]
+    fn window_not_a_number() {
+        parse_windows("1,ten");
+    }
+
+    #[test]
+    #[should_panic(expected = "Density windows must be positive, got 0")]
+    fn window_zero() {
+        parse_windows("0");
+    }
+
+    #[test]
+    #[should_panic(expected = "Density windows must be positive, got NaN")]
+    fn window_nan() {
+        parse_windows("NaN");
+    }
+
+    #[test]
+    #[should_panic(expected = "Density windows must be positive, got inf")]
+    fn window_infinite() {
+        parse_windows("inf");
+    }
+
+    #[test]
+    #[should_panic(expected = "Density window 10.0 is given twice")]
+    fn window_twice() {
+        parse_windows("10,4,10.0");
+    }
+
+    #[test]
+    fn tempo_changes() {
+        // 120 BPM, then 240 from beat 8
+        let tempo_map = TempoMap::from_difficulty(
+            120.0,
+            &json!({"version": "3.2.0", "bpmEvents": [{"b": 8, "m": 240}]}),
+        );
+        assert_eq!(tempo_map.seconds(4.0), 2.0);
+        assert_eq!(tempo_map.seconds(8.0), 4.0);
+        assert_eq!(tempo_map.seconds(12.0), 5.0);
+    }
+
+    #[test]
+    fn peak_across_tempo_changes() {
+        // a note every beat, which is twice as dense after the tempo doubles
+        let tempo_map = TempoMap::new(120.0, vec![(8.0, 240.0)]);
+        let times = (0..=16)
+            .map(|b| tempo_map.seconds(b as f64))
+            .collect::<Vec<_>>();
+        assert_eq!(peak_density(&times, 1.0), 5.0);
+        assert_eq!(peak_density(&times[..=8], 1.0), 3.0);
+        // the whole song, 17 notes in 6 seconds
+        assert_eq!(peak_density(&times, 6.0), 2.83);
+    }
+}
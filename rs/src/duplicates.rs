@@ -0,0 +1,170 @@
+use crate::LevelInfo;
+use clap::ArgMatches;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateKind {
+    /// Several folders hash to the same level ID, so they are byte-for-byte the same map.
+    SameHash,
+    /// Same song, artist and mapper with different level IDs, most likely updated versions.
+    SameSong,
+}
+
+#[derive(Serialize)]
+pub struct Copy {
+    pub folder: String,
+    pub id: String,
+    /// Plays saved for this level ID across all difficulties and selected players.
+    pub plays: u32,
+}
+
+#[derive(Serialize)]
+pub struct DuplicateGroup {
+    pub kind: DuplicateKind,
+    pub song: String,
+    pub artist: String,
+    pub mapper: String,
+    pub copies: Vec<Copy>,
+}
+
+/// Prints the groups of custom levels that are duplicates of each other.
+pub fn run(level_stats: &[LevelInfo], args: &ArgMatches) {
+    let groups = find(level_stats);
+    if args.is_present("json") {
+        println!("{}", serde_json::to_string_pretty(&groups).unwrap());
+        return;
+    }
+    for group in &groups {
+        println!(
+            "{} - {} ({}): {}",
+            group.song,
+            group.artist,
+            group.mapper,
+            match group.kind {
+                DuplicateKind::SameHash => "identical copies",
+                DuplicateKind::SameSong => "different versions",
+            }
+        );
+        for copy in &group.copies {
+            if copy.plays > 0 {
+                println!("  {} {} ({} plays)", copy.folder, copy.id, copy.plays);
+            } else {
+                println!("  {} {} (no scores)", copy.folder, copy.id);
+            }
+        }
+    }
+    println!("{} groups of duplicates", groups.len());
+}
+
+/// Groups custom levels by level ID, then the remaining levels by song, artist and mapper
+/// (ignoring case and surrounding whitespace).
+pub fn find(level_stats: &[LevelInfo]) -> Vec<DuplicateGroup> {
+    // with --all-players, every player has a copy of each level, so add up their plays per folder
+    let mut custom_levels = Vec::new();
+    let mut plays_by_folder = HashMap::<&str, u32>::new();
+    for l in level_stats {
+        let folder = match &l.folder {
+            Some(folder) => folder.as_str(),
+            None => continue,
+        };
+        let plays = l
+            .characteristics
+            .values()
+            .flat_map(|c| c.difficulties.values())
+            .map(|d| d.plays)
+            .sum::<u32>();
+        match plays_by_folder.get_mut(folder) {
+            Some(total) => *total += plays,
+            None => {
+                plays_by_folder.insert(folder, plays);
+                custom_levels.push(l);
+            }
+        }
+    }
+    let copy = |l: &LevelInfo| Copy {
+        folder: l.folder.clone().unwrap(),
+        id: l.id.clone(),
+        plays: plays_by_folder[l.folder.as_deref().unwrap()],
+    };
+    let group = |kind, levels: &[&LevelInfo]| {
+        let mut copies = levels.iter().map(|l| copy(l)).collect::<Vec<_>>();
+        copies.sort_by(|a, b| a.folder.cmp(&b.folder));
+        DuplicateGroup {
+            kind,
+            song: levels[0].song.clone(),
+            artist: levels[0].artist.clone(),
+            mapper: levels[0].mapper.clone(),
+            copies,
+        }
+    };
+
+    let mut groups = Vec::new();
+    let mut by_id = BTreeMap::<&str, Vec<&LevelInfo>>::new();
+    for l in &custom_levels {
+        by_id.entry(&l.id).or_default().push(l);
+    }
+    for levels in by_id.values().filter(|levels| levels.len() > 1) {
+        groups.push(group(DuplicateKind::SameHash, levels));
+    }
+
+    // one level per ID, so identical copies aren't reported again as versions of themselves
+    let mut by_song = BTreeMap::<(String, String, String), Vec<&LevelInfo>>::new();
+    for levels in by_id.values() {
+        let l = levels[0];
+        let key = (
+            l.song.trim().to_lowercase(),
+            l.artist.trim().to_lowercase(),
+            l.mapper.trim().to_lowercase(),
+        );
+        by_song.entry(key).or_default().push(l);
+    }
+    for levels in by_song.values().filter(|levels| levels.len() > 1) {
+        groups.push(group(DuplicateKind::SameSong, levels));
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{add_difficulty, difficulty, level};
+
+    fn copy(folder: &str, id: &str, player: &str, rank: &str) -> LevelInfo {
+        let mut l = level(id, player);
+        l.folder = Some(folder.to_owned());
+        add_difficulty(&mut l, "Expert", difficulty(8.0, rank));
+        l
+    }
+
+    #[test]
+    fn plays_of_every_player() {
+        let level_stats = [
+            copy("a (Song)", "custom_level_AAAA", "Alice", "A"),
+            copy("b (Song)", "custom_level_BBBB", "Alice", ""),
+            copy("c (Song)", "custom_level_AAAA", "Alice", "A"),
+            copy("a (Song)", "custom_level_AAAA", "Bob", "S"),
+            copy("b (Song)", "custom_level_BBBB", "Bob", "S"),
+            copy("c (Song)", "custom_level_AAAA", "Bob", "S"),
+        ];
+        let groups = find(&level_stats);
+        assert_eq!(groups.len(), 2);
+        assert!(matches!(groups[0].kind, DuplicateKind::SameHash));
+        let copies = |g: &DuplicateGroup| {
+            g.copies
+                .iter()
+                .map(|c| (c.folder.clone(), c.plays))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            copies(&groups[0]),
+            [("a (Song)".to_owned(), 2), ("c (Song)".to_owned(), 2)]
+        );
+        assert!(matches!(groups[1].kind, DuplicateKind::SameSong));
+        assert_eq!(
+            copies(&groups[1]),
+            [("a (Song)".to_owned(), 2), ("b (Song)".to_owned(), 1)]
+        );
+    }
+}
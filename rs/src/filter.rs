@@ -0,0 +1,506 @@
+use crate::{LevelDifficulty, LevelInfo, DIFFICULTY_NAME_MAP, SCORE_RANK_MAP};
+
+/// One output row: a difficulty and the level it belongs to.
+#[derive(Clone, Copy)]
+pub struct Row<'a> {
+    pub level: &'a LevelInfo,
+    pub characteristic: &'a str,
+    pub difficulty: &'a str,
+    pub stats: &'a LevelDifficulty,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FieldType {
+    Number,
+    Text,
+    Bool,
+    /// Compared by position in `SCORE_RANK_MAP`, so `rank < "S"` is A and below.
+    Rank,
+    /// Compared by position in `DIFFICULTY_NAME_MAP`.
+    Difficulty,
+}
+
+/// A field value, with ranks and difficulties as their position. Missing values are `Null`.
+#[derive(Clone, PartialEq, PartialOrd, Debug)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    Text(String),
+}
+
+/// The type of a field, or `None` if there's no field with that name. Peak density fields only
+/// exist for the `--windows` given.
+pub fn field_type(name: &str, windows: &[f64]) -> Option<FieldType> {
+    Some(match name {
+        "song" | "artist" | "mapper" | "environment" | "characteristic" | "label" | "combo"
+        | "requirements" | "suggestions" | "id" | "player" => FieldType::Text,
+        "bpm" | "duration" | "notes" | "nps" | "tech" | "speed" | "score" | "max_score"
+        | "accuracy" | "plays" => FieldType::Number,
+        "valid" | "fc" | "favorite" | "missing_locally" => FieldType::Bool,
+        "rank" => FieldType::Rank,
+        "difficulty" => FieldType::Difficulty,
+        _ if window(name).is_some_and(|w| windows.contains(&w)) => FieldType::Number,
+        _ => return None,
+    })
+}
+
+/// The error for a field that `field_type` doesn't know.
+pub fn unknown_field(name: &str) -> String {
+    match window(name) {
+        Some(w) => format!("{} needs {} in --windows", name, w),
+        None => format!("unknown field {}", name),
+    }
+}
+
+/// The window of a peak density field like `np10s`.
+fn window(name: &str) -> Option<f64> {
+    name.strip_prefix("np")?.strip_suffix('s')?.parse().ok()
+}
+
+impl Row<'_> {
+    /// The value of a field that `field_type` knows.
+    pub fn value(&self, name: &str) -> Value {
+        let l = self.level;
+        let d = self.stats;
+        let text = |s: &str| Value::Text(s.to_owned());
+        let number = |n: Option<f64>| n.map_or(Value::Null, Value::Number);
+        match name {
+            "song" => text(&l.song),
+            "artist" => text(&l.artist),
+            "mapper" => text(&l.mapper),
+            "environment" => text(&l.environment),
+            "characteristic" => text(self.characteristic),
+            "label" => text(d.label.as_deref().unwrap_or(self.difficulty)),
+            "combo" => text(&d.combo),
+            "requirements" => text(&d.requirements.join(", ")),
+            "suggestions" => text(&d.suggestions.join(", ")),
+            "id" => text(&l.id),
+            "player" => text(&l.player),
+            "bpm" => Value::Number(l.bpm),
+            "duration" => Value::Number(l.duration),
+            "notes" => number(d.notes.map(|n| n as f64)),
+            "nps" => number(d.nps),
+            "tech" => number(d.rating.as_ref().map(|r| r.tech)),
+            "speed" => number(d.rating.as_ref().map(|r| r.speed)),
+            "score" => Value::Number(d.score as f64),
+            "max_score" => number(d.max_score.map(|s| s as f64)),
+            "accuracy" => number(d.accuracy()),
+            "plays" => Value::Number(d.plays as f64),
+            "valid" => Value::Bool(d.valid),
+            "fc" => Value::Bool(d.combo == "FC"),
+            "favorite" => Value::Bool(l.favorite),
+            "missing_locally" => Value::Bool(l.missing_locally),
+            "rank" => position(&SCORE_RANK_MAP, &d.rank),
+            "difficulty" => position(&DIFFICULTY_NAME_MAP, self.difficulty),
+            _ => {
+                let w = window(name).unwrap();
+                number(d.peak_nps.iter().find(|p| p.window == w).map(|p| p.nps))
+            }
+        }
+    }
+}
+
+fn position(names: &[&str], name: &str) -> Value {
+    names
+        .iter()
+        .position(|n| *n == name)
+        .map_or(Value::Null, |i| Value::Number(i as f64))
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug)]
+pub enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(String, Op, Value),
+    /// A bool field on its own, e.g. `valid`.
+    Field(String),
+}
+
+impl Expr {
+    pub fn matches(&self, row: &Row) -> bool {
+        match self {
+            Expr::Or(a, b) => a.matches(row) || b.matches(row),
+            Expr::And(a, b) => a.matches(row) && b.matches(row),
+            Expr::Not(e) => !e.matches(row),
+            Expr::Field(name) => row.value(name) == Value::Bool(true),
+            Expr::Compare(name, op, value) => {
+                let field = row.value(name);
+                // missing values only equal each other
+                if field == Value::Null || *value == Value::Null {
+                    return match op {
+                        Op::Eq => field == *value,
+                        Op::Ne => field != *value,
+                        _ => false,
+                    };
+                }
+                match op {
+                    Op::Eq => field == *value,
+                    Op::Ne => field != *value,
+                    Op::Lt => field < *value,
+                    Op::Le => field <= *value,
+                    Op::Gt => field > *value,
+                    Op::Ge => field >= *value,
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Ident(String),
+    Number(f64),
+    Text(String),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let chars = source.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let two = |t: Token| (t, 2);
+        let (token, len) = match (c, next) {
+            _ if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            ('&', Some('&')) => two(Token::And),
+            ('|', Some('|')) => two(Token::Or),
+            ('=', Some('=')) => two(Token::Op(Op::Eq)),
+            ('!', Some('=')) => two(Token::Op(Op::Ne)),
+            ('<', Some('=')) => two(Token::Op(Op::Le)),
+            ('>', Some('=')) => two(Token::Op(Op::Ge)),
+            ('<', _) => (Token::Op(Op::Lt), 1),
+            ('>', _) => (Token::Op(Op::Gt), 1),
+            ('!', _) => (Token::Not, 1),
+            ('(', _) => (Token::Open, 1),
+            (')', _) => (Token::Close, 1),
+            ('"', _) | ('\'', _) => {
+                let mut text = String::new();
+                let mut end = i + 1;
+                loop {
+                    match chars.get(end) {
+                        None => return Err(format!("unterminated string at {}", i)),
+                        Some('\\') if end + 1 < chars.len() => {
+                            text.push(chars[end + 1]);
+                            end += 2;
+                        }
+                        Some(q) if *q == c => break,
+                        Some(ch) => {
+                            text.push(*ch);
+                            end += 1;
+                        }
+                    }
+                }
+                (Token::Text(text), end + 1 - i)
+            }
+            _ if c.is_ascii_digit() || c == '.' || (c == '-' && next.is_some()) => {
+                let len = chars[i + 1..]
+                    .iter()
+                    .take_while(|ch| ch.is_ascii_digit() || **ch == '.')
+                    .count()
+                    + 1;
+                let number = chars[i..i + len].iter().collect::<String>();
+                match number.parse() {
+                    Ok(n) => (Token::Number(n), len),
+                    Err(_) => return Err(format!("invalid number {} at {}", number, i)),
+                }
+            }
+            _ if c.is_ascii_alphabetic() || c == '_' => {
+                // peak densities can have fractional windows, like np1.5s
+                let density = chars[i..].starts_with(&['n', 'p']);
+                let len = chars[i..]
+                    .iter()
+                    .take_while(|ch| {
+                        ch.is_ascii_alphanumeric() || **ch == '_' || (density && **ch == '.')
+                    })
+                    .count();
+                (Token::Ident(chars[i..i + len].iter().collect()), len)
+            }
+            _ => return Err(format!("unexpected {} at {}", c, i)),
+        };
+        tokens.push(token);
+        i += len;
+    }
+    Ok(tokens)
+}
+
+/// Parses a filter expression, checking field names and the types of comparisons.
+///
+/// ```text
+/// expr       = and ("||" and)*
+/// and        = not ("&&" not)*
+/// not        = "!" not | "(" expr ")" | comparison | bool field
+/// comparison = field op literal | literal op field
+/// literal    = number | "string" | 'string' | true | false | null
+/// ```
+pub fn parse(source: &str, windows: &[f64]) -> Result<Expr, String> {
+    let tokens = tokenize(source)?;
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        windows,
+    };
+    let expr = parser.or()?;
+    match parser.tokens.get(parser.pos) {
+        None => Ok(expr),
+        Some(t) => Err(format!("unexpected {:?} after the expression", t)),
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    windows: &'a [f64],
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.not()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            Some(Token::Not) => {
+                self.pos += 1;
+                Ok(Expr::Not(Box::new(self.not()?)))
+            }
+            Some(Token::Open) => {
+                self.pos += 1;
+                let expr = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => Err("missing )".to_owned()),
+                }
+            }
+            _ => self.comparison(),
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let left = self.next().ok_or("unexpected end of the expression")?;
+        let field_type = |name: &str| field_type(name, self.windows);
+        let op = match self.peek() {
+            Some(Token::Op(op)) => *op,
+            _ => {
+                // a bool field on its own
+                return match left {
+                    Token::Ident(name) if field_type(&name) == Some(FieldType::Bool) => {
+                        Ok(Expr::Field(name))
+                    }
+                    Token::Ident(name) if field_type(&name).is_some() => Err(format!(
+                        "{} is not true or false, compare it to something",
+                        name
+                    )),
+                    Token::Ident(name) => Err(unknown_field(&name)),
+                    t => Err(format!("expected a field, found {:?}", t)),
+                };
+            }
+        };
+        self.pos += 1;
+        let right = self.next().ok_or("unexpected end of the expression")?;
+        // keep the field on the left, flipping the operator if needed
+        let (name, op, literal) = match (left, right) {
+            (Token::Ident(name), literal) if field_type(&name).is_some() => (name, op, literal),
+            (literal, Token::Ident(name)) if field_type(&name).is_some() => {
+                let flipped = match op {
+                    Op::Lt => Op::Gt,
+                    Op::Le => Op::Ge,
+                    Op::Gt => Op::Lt,
+                    Op::Ge => Op::Le,
+                    op => op,
+                };
+                (name, flipped, literal)
+            }
+            (Token::Ident(name), _) | (_, Token::Ident(name)) => return Err(unknown_field(&name)),
+            _ => return Err("a comparison needs a field".to_owned()),
+        };
+        let field_type = field_type(&name).unwrap();
+        let value = match (field_type, literal) {
+            (_, Token::Ident(keyword)) if keyword == "null" => Value::Null,
+            (FieldType::Bool, Token::Ident(keyword)) if keyword == "true" => Value::Bool(true),
+            (FieldType::Bool, Token::Ident(keyword)) if keyword == "false" => Value::Bool(false),
+            (FieldType::Number, Token::Number(n)) => Value::Number(n),
+            (FieldType::Text, Token::Text(s)) => Value::Text(s),
+            (FieldType::Rank, Token::Text(s)) => match position(&SCORE_RANK_MAP, &s) {
+                Value::Null => return Err(format!("unknown rank {:?}", s)),
+                v => v,
+            },
+            (FieldType::Difficulty, Token::Text(s)) => match position(&DIFFICULTY_NAME_MAP, &s) {
+                Value::Null => return Err(format!("unknown difficulty {:?}", s)),
+                v => v,
+            },
+            (field_type, literal) => {
+                return Err(format!(
+                    "can't compare {} ({:?}) with {:?}",
+                    name, field_type, literal
+                ))
+            }
+        };
+        if field_type == FieldType::Bool && !matches!(op, Op::Eq | Op::Ne) {
+            return Err(format!("{} can only be compared with == or !=", name));
+        }
+        Ok(Expr::Compare(name, op, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{difficulty, level};
+
+    /// Whether `source` matches an Expert difficulty with the given NP10S and rank.
+    fn matches(source: &str, np10s: f64, rank: &str) -> bool {
+        let level = level("custom_level_1A2B", "Alice");
+        let stats = difficulty(np10s, rank);
+        let row = Row {
+            level: &level,
+            characteristic: "Standard",
+            difficulty: "Expert",
+            stats: &stats,
+        };
+        parse(source, &[1.5, 10.0]).unwrap().matches(&row)
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        // false || (true && false)
+        assert!(!matches(
+            "np10s > 10 || np10s > 5 && rank == \"S\"",
+            8.0,
+            "A"
+        ));
+        // (false || true) && false
+        assert!(!matches(
+            "(np10s > 10 || np10s > 5) && rank == \"S\"",
+            8.0,
+            "A"
+        ));
+        // true || (false && false)
+        assert!(matches(
+            "np10s > 5 || np10s > 10 && rank == \"S\"",
+            8.0,
+            "A"
+        ));
+    }
+
+    #[test]
+    fn not_and_parentheses() {
+        assert!(matches("!(np10s > 10)", 8.0, "A"));
+        assert!(!matches("!np10s < 10", 8.0, "A"));
+        assert!(matches("!!valid", 8.0, "A"));
+        assert!(matches(
+            "!(rank == \"S\" || rank == \"SS\") && valid",
+            8.0,
+            "A"
+        ));
+    }
+
+    #[test]
+    fn literal_first_comparisons_are_flipped() {
+        assert!(matches("8 < np10s", 9.0, ""));
+        assert!(!matches("8 < np10s", 7.0, ""));
+        assert!(matches("10 >= np10s", 10.0, ""));
+        assert!(matches("\"A\" == rank", 8.0, "A"));
+    }
+
+    #[test]
+    fn fractional_window() {
+        assert!(matches("np1.5s == 16", 8.0, ""));
+        assert_eq!(field_type("np1.5s", &[1.5]), Some(FieldType::Number));
+    }
+
+    #[test]
+    fn ranks_compare_by_position() {
+        assert!(matches("rank < \"S\"", 8.0, "A"));
+        assert!(!matches("rank < \"S\"", 8.0, "SS"));
+        // unplayed difficulties have no rank, which is neither below nor above any rank
+        assert!(!matches("rank < \"S\"", 8.0, ""));
+        assert!(!matches("rank >= \"S\"", 8.0, ""));
+    }
+
+    #[test]
+    fn null_only_equals_null() {
+        assert!(matches("rank == null", 8.0, ""));
+        assert!(!matches("rank != null", 8.0, ""));
+        assert!(!matches("rank == null", 8.0, "A"));
+        assert!(matches("rank != null", 8.0, "A"));
+        assert!(matches("max_score == null", 8.0, "A"));
+    }
+
+    #[test]
+    fn errors() {
+        let error = |source| parse(source, &[10.0]).unwrap_err();
+        assert_eq!(error("np11 > 8"), "unknown field np11");
+        assert_eq!(error("np30s > 8"), "np30s needs 30 in --windows");
+        assert_eq!(error("8 < np1.5s"), "np1.5s needs 1.5 in --windows");
+        assert_eq!(error("!favourite"), "unknown field favourite");
+        assert_eq!(error("8 < foo"), "unknown field foo");
+        assert_eq!(
+            error("np10s"),
+            "np10s is not true or false, compare it to something"
+        );
+        assert_eq!(
+            error("np10s > \"8\""),
+            "can't compare np10s (Number) with Text(\"8\")"
+        );
+        assert_eq!(
+            error("song == 1"),
+            "can't compare song (Text) with Number(1.0)"
+        );
+        assert_eq!(error("rank < \"Q\""), "unknown rank \"Q\"");
+        assert_eq!(
+            error("valid < true"),
+            "valid can only be compared with == or !="
+        );
+        assert_eq!(error("(valid"), "missing )");
+        assert_eq!(
+            error("valid valid"),
+            "unexpected Ident(\"valid\") after the expression"
+        );
+    }
+}
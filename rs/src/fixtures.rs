@@ -0,0 +1,74 @@
+//! Levels and difficulties for tests, with only the fields the test cares about set.
+
+use crate::density::PeakDensity;
+use crate::{LevelCharacteristic, LevelDifficulty, LevelInfo};
+use std::collections::HashMap;
+
+/// A level without difficulties.
+pub fn level(id: &str, player: &str) -> LevelInfo {
+    LevelInfo {
+        song: "Song".to_owned(),
+        artist: "Artist".to_owned(),
+        mapper: "Mapper".to_owned(),
+        bpm: 120.0,
+        environment: "DefaultEnvironment".to_owned(),
+        duration: 60.0,
+        characteristics: HashMap::new(),
+        contributors: Vec::new(),
+        id: id.to_owned(),
+        folder: None,
+        cover_image: None,
+        missing_locally: false,
+        player: player.to_owned(),
+        player_index: 0,
+        favorite: false,
+    }
+}
+
+/// A difficulty with peak densities for 1.5 and 10 second windows, unplayed if `rank` is empty.
+pub fn difficulty(np10s: f64, rank: &str) -> LevelDifficulty {
+    LevelDifficulty {
+        valid: true,
+        plays: if rank.is_empty() { 0 } else { 1 },
+        rank: rank.to_owned(),
+        combo: "".to_owned(),
+        score: 0,
+        peak_nps: vec![
+            PeakDensity {
+                window: 1.5,
+                nps: np10s * 2.0,
+            },
+            PeakDensity {
+                window: 10.0,
+                nps: np10s,
+            },
+        ],
+        timeline: None,
+        patterns: None,
+        rating: None,
+        max_score: None,
+        requirements: Vec::new(),
+        suggestions: Vec::new(),
+        label: None,
+        color_left: None,
+        color_right: None,
+        warnings: Vec::new(),
+        information: Vec::new(),
+        lighting: None,
+        rotation: None,
+        nps: None,
+        notes: None,
+    }
+}
+
+/// Adds a Standard difficulty to `level`.
+pub fn add_difficulty(level: &mut LevelInfo, name: &str, difficulty: LevelDifficulty) {
+    level
+        .characteristics
+        .entry("Standard".to_owned())
+        .or_insert_with(|| LevelCharacteristic {
+            difficulties: HashMap::new(),
+        })
+        .difficulties
+        .insert(name.to_owned(), difficulty);
+}
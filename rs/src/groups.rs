@@ -0,0 +1,163 @@
+use crate::density::round2;
+use crate::{html, LevelInfo};
+use clap::ArgMatches;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+pub const GROUP_FIELDS: [&str; 3] = ["mapper", "artist", "environment"];
+
+/// Totals for all the difficulties of the levels sharing a mapper, artist or environment, for one
+/// player.
+#[derive(Serialize, Default)]
+pub struct Group {
+    pub player: String,
+    pub name: String,
+    pub maps: u32,
+    pub difficulties: u32,
+    pub average_np10s: Option<f64>,
+    pub peak_np10s: Option<f64>,
+    /// Average accuracy of the played difficulties with a max score.
+    pub average_accuracy: Option<f64>,
+    pub plays: u32,
+    /// Percentage of played difficulties that are full combos.
+    pub fc_rate: Option<f64>,
+}
+
+/// Groups levels by player and `field`, one of `GROUP_FIELDS`, since every player has their own
+/// copy of each level. Sorted by player in save file order, then name. Levels with an empty value,
+/// like scores for levels that aren't installed, are left out.
+pub fn group_by(level_stats: &[LevelInfo], field: &str) -> Vec<Group> {
+    let mut levels_by_name = BTreeMap::<(usize, &str), Vec<&LevelInfo>>::new();
+    for l in level_stats {
+        let name = match field {
+            "mapper" => &l.mapper,
+            "artist" => &l.artist,
+            "environment" => &l.environment,
+            _ => panic!("Can't group by {}", field),
+        };
+        if !name.is_empty() {
+            levels_by_name
+                .entry((l.player_index, name))
+                .or_default()
+                .push(l);
+        }
+    }
+
+    let mut groups = Vec::with_capacity(levels_by_name.len());
+    for ((_, name), levels) in levels_by_name {
+        let ids = levels.iter().map(|l| &l.id).collect::<HashSet<_>>();
+        let difficulties = levels
+            .iter()
+            .flat_map(|l| l.characteristics.values())
+            .flat_map(|c| c.difficulties.values())
+            .collect::<Vec<_>>();
+        let np10s = difficulties
+            .iter()
+            .filter_map(|d| d.peak_nps.iter().find(|p| p.window == 10.0))
+            .map(|p| p.nps)
+            .collect::<Vec<_>>();
+        let played = difficulties
+            .iter()
+            .filter(|d| d.plays > 0)
+            .collect::<Vec<_>>();
+        let accuracies = played
+            .iter()
+            .filter_map(|d| d.accuracy())
+            .collect::<Vec<_>>();
+        groups.push(Group {
+            player: levels[0].player.clone(),
+            name: name.to_owned(),
+            maps: ids.len() as u32,
+            difficulties: difficulties.len() as u32,
+            average_np10s: average(&np10s),
+            peak_np10s: np10s.iter().cloned().reduce(f64::max),
+            average_accuracy: average(&accuracies),
+            plays: played.iter().map(|d| d.plays).sum(),
+            fc_rate: if played.is_empty() {
+                None
+            } else {
+                Some(round2(
+                    played.iter().filter(|d| d.combo == "FC").count() as f64 * 100.0
+                        / played.len() as f64,
+                ))
+            },
+        });
+    }
+    groups
+}
+
+fn average(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        None
+    } else {
+        Some(round2(values.iter().sum::<f64>() / values.len() as f64))
+    }
+}
+
+/// Prints the groups as CSV, or JSON with `--json`, or writes them to groups.html with
+/// `--format html`. The CSV and HTML have a `Player` column with `--all-players`.
+pub fn run(level_stats: &[LevelInfo], args: &ArgMatches, groups_args: &ArgMatches) {
+    let field = groups_args.value_of("by").unwrap();
+    let groups = group_by(level_stats, field);
+    if groups_args.is_present("json") {
+        println!("{}", serde_json::to_string_pretty(&groups).unwrap());
+        return;
+    }
+    let (header, records) = table(&groups, field, args.is_present("all-players"));
+    if args.value_of("format") == Some("html") {
+        let path = Path::new("groups.html");
+        let title = format!("Beat Saber Stats by {}", field);
+        html::write_table(path, &title, "groups", &header, &records);
+        println!("Wrote {}", path.display());
+        return;
+    }
+    let mut wtr = csv::Writer::from_writer(std::io::stdout());
+    wtr.write_record(&header).unwrap();
+    for record in &records {
+        wtr.write_record(record).unwrap();
+    }
+    wtr.flush().unwrap();
+}
+
+/// The header and a record per group, with the name column named after `field`.
+fn table(groups: &[Group], field: &str, all_players: bool) -> (Vec<String>, Vec<Vec<String>>) {
+    let optional = |v: Option<f64>| v.map(|v| v.to_string()).unwrap_or_default();
+    let mut name_header = field.to_owned();
+    name_header[..1].make_ascii_uppercase();
+    let mut header = [
+        name_header.as_str(),
+        "Maps",
+        "Difficulties",
+        "Average NP10S",
+        "Peak NP10S",
+        "Average Accuracy %",
+        "Plays",
+        "FC Rate %",
+    ]
+    .map(|h| h.to_owned())
+    .to_vec();
+    if all_players {
+        header.insert(0, "Player".to_owned());
+    }
+    let records = groups
+        .iter()
+        .map(|group| {
+            let mut record = vec![
+                group.name.clone(),
+                group.maps.to_string(),
+                group.difficulties.to_string(),
+                optional(group.average_np10s),
+                optional(group.peak_np10s),
+                optional(group.average_accuracy),
+                group.plays.to_string(),
+                optional(group.fc_rate),
+            ];
+            if all_players {
+                record.insert(0, group.player.clone());
+            }
+            record
+        })
+        .collect();
+    (header, records)
+}
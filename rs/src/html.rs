@@ -0,0 +1,330 @@
+use crate::filter::Row;
+use crate::playlist::base64;
+use crate::summary::Summary;
+use crate::LevelInfo;
+use log::warn;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+const SPARKLINE_WIDTH: f64 = 120.0;
+const SPARKLINE_HEIGHT: f64 = 24.0;
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 1em; background: #fafafa; color: #222; }
+.cards { display: flex; flex-wrap: wrap; gap: 1em; margin-bottom: 1em; }
+.card { background: #fff; border: 1px solid #ddd; border-radius: 6px; padding: 0.5em 1em; }
+.card h2 { margin: 0.2em 0; font-size: 1.1em; }
+.card dl { display: grid; grid-template-columns: auto auto; gap: 0 1em; margin: 0; }
+.card dd { margin: 0; text-align: right; }
+table { border-collapse: collapse; background: #fff; font-size: 0.85em; }
+th, td { border: 1px solid #ddd; padding: 2px 6px; white-space: nowrap; }
+th { position: sticky; top: 0; background: #eee; cursor: pointer; user-select: none; }
+th.asc::after { content: ' \\25B2'; }
+th.desc::after { content: ' \\25BC'; }
+tbody tr:nth-child(even) { background: #f4f4f4; }
+td.cover { width: 32px; height: 32px; padding: 0; background-size: cover; }
+polyline { fill: none; stroke: #36c; stroke-width: 1; }
+#filter { margin-bottom: 0.5em; width: 30em; }
+";
+
+// sorts by a column when its header is clicked, numerically if every value is a number, and hides
+// rows that don't contain the filter text
+const SCRIPT: &str = "
+const table = document.getElementById('stats');
+const body = table.tBodies[0];
+const filter = document.getElementById('filter');
+const count = document.getElementById('count');
+function updateCount() {
+  const shown = [...body.rows].filter(r => !r.hidden).length;
+  count.textContent = shown + ' of ' + body.rows.length + ' ' + count.dataset.noun;
+}
+filter.addEventListener('input', () => {
+  const text = filter.value.toLowerCase();
+  for (const row of body.rows) {
+    row.hidden = !row.textContent.toLowerCase().includes(text);
+  }
+  updateCount();
+});
+[...table.tHead.rows[0].cells].forEach((th, i) => th.addEventListener('click', () => {
+  const descending = th.classList.contains('asc');
+  for (const other of table.tHead.rows[0].cells) other.classList.remove('asc', 'desc');
+  th.classList.add(descending ? 'desc' : 'asc');
+  const value = r => r.cells[i].dataset.sort ?? r.cells[i].textContent;
+  const rows = [...body.rows];
+  const numeric = rows.every(r => value(r) === '' || !isNaN(value(r)));
+  rows.sort((a, b) => {
+    const x = value(a), y = value(b);
+    if (x === '' || y === '') return (x === '') - (y === '');
+    const order = numeric ? x - y : x.localeCompare(y);
+    return descending ? -order : order;
+  });
+  body.append(...rows);
+}));
+updateCount();
+";
+
+/// Writes a single-file HTML report: summary cards per player and a table of the output rows with
+/// their covers and density sparklines, sortable by clicking a column and filterable by text.
+pub fn write(
+    path: &Path,
+    header: &[String],
+    rows: &[Row],
+    records: &[Vec<String>],
+    level_stats: &[LevelInfo],
+    custom_levels_path: &Path,
+) {
+    let mut css = String::new();
+    let covers = cover_classes(level_stats, custom_levels_path, &mut css);
+    let mut html = start("Beat Saber Stats", &css);
+
+    html.push_str("<div class=\"cards\">\n");
+    // players in save file order, by index since names can repeat
+    let mut players = Vec::<(usize, &str)>::new();
+    for l in level_stats {
+        if !players.iter().any(|p| p.0 == l.player_index) {
+            players.push((l.player_index, &l.player));
+        }
+    }
+    for (index, name) in players {
+        let levels = level_stats
+            .iter()
+            .filter(|l| l.player_index == index)
+            .collect::<Vec<_>>();
+        summary_card(&mut html, &Summary::new(name, &levels));
+    }
+    html.push_str("</div>\n");
+
+    table_start(&mut html, "difficulties");
+    html.push_str("<th>Cover</th>");
+    for h in header {
+        write!(html, "<th>{}</th>", escape(h)).unwrap();
+    }
+    html.push_str("<th>Density</th></tr></thead>\n<tbody>\n");
+    // one scale for every sparkline, so they can be compared
+    let max_nps = rows
+        .iter()
+        .filter_map(|r| r.stats.timeline.as_ref())
+        .flat_map(|t| t.notes.iter().map(move |&n| n as f64 / t.bucket))
+        .fold(0.0, f64::max);
+    for (row, record) in rows.iter().zip(records) {
+        html.push_str("<tr>");
+        match covers.get(row.level.id.as_str()) {
+            Some(class) => {
+                write!(html, "<td class=\"cover {}\" data-sort=\"\"></td>", class).unwrap()
+            }
+            None => html.push_str("<td data-sort=\"\"></td>"),
+        }
+        for value in record {
+            write!(html, "<td>{}</td>", escape(value)).unwrap();
+        }
+        match &row.stats.timeline {
+            Some(timeline) if max_nps > 0.0 => {
+                let nps = timeline
+                    .notes
+                    .iter()
+                    .map(|&n| n as f64 / timeline.bucket)
+                    .collect::<Vec<_>>();
+                let peak = nps.iter().cloned().fold(0.0, f64::max);
+                write!(
+                    html,
+                    "<td data-sort=\"{}\">{}</td>",
+                    peak,
+                    sparkline(&nps, max_nps)
+                )
+                .unwrap();
+            }
+            _ => html.push_str("<td data-sort=\"\"></td>"),
+        }
+        html.push_str("</tr>\n");
+    }
+    end(&mut html);
+    fs::write(path, html).unwrap();
+}
+
+/// Writes a single-file HTML page with a table of `records`, which is sortable and filterable like
+/// the stats report. `noun` is what the rows are, for the row count.
+pub fn write_table(
+    path: &Path,
+    title: &str,
+    noun: &str,
+    header: &[String],
+    records: &[Vec<String>],
+) {
+    let mut html = start(title, "");
+    table_start(&mut html, noun);
+    for h in header {
+        write!(html, "<th>{}</th>", escape(h)).unwrap();
+    }
+    html.push_str("</tr></thead>\n<tbody>\n");
+    for record in records {
+        html.push_str("<tr>");
+        for value in record {
+            write!(html, "<td>{}</td>", escape(value)).unwrap();
+        }
+        html.push_str("</tr>\n");
+    }
+    end(&mut html);
+    fs::write(path, html).unwrap();
+}
+
+/// The page up to the end of the heading.
+fn start(title: &str, css: &str) -> String {
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    write!(html, "<title>{}</title>\n<style>", escape(title)).unwrap();
+    html.push_str(STYLE);
+    html.push_str(css);
+    write!(
+        html,
+        "</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+        escape(title)
+    )
+    .unwrap();
+    html
+}
+
+/// The filter box and the table up to its first header cell.
+fn table_start(html: &mut String, noun: &str) {
+    writeln!(
+        html,
+        "<input id=\"filter\" type=\"search\" placeholder=\"Filter\"> <span id=\"count\" data-noun=\"{}\"></span>",
+        noun
+    )
+    .unwrap();
+    html.push_str("<table id=\"stats\">\n<thead><tr>");
+}
+
+fn end(html: &mut String) {
+    html.push_str("</tbody>\n</table>\n<script>");
+    html.push_str(SCRIPT);
+    html.push_str("</script>\n</body>\n</html>\n");
+}
+
+/// Adds a CSS class with each installed level's cover as a data URI, so a cover is only embedded
+/// once however many difficulties it has. Returns the class name by level ID.
+fn cover_classes<'a>(
+    level_stats: &'a [LevelInfo],
+    custom_levels_path: &Path,
+    css: &mut String,
+) -> HashMap<&'a str, String> {
+    let mut classes = HashMap::new();
+    // with --all-players, every player has a copy of each level
+    let mut seen = HashSet::new();
+    for l in level_stats {
+        if !seen.insert(l.id.as_str()) {
+            continue;
+        }
+        let (folder, cover_image) = match (&l.folder, &l.cover_image) {
+            (Some(folder), Some(cover_image)) => (folder, cover_image),
+            _ => continue,
+        };
+        let cover_path = custom_levels_path.join(folder).join(cover_image);
+        let mime = match cover_path
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("png") => "image/png",
+            Some("jpg" | "jpeg") => "image/jpeg",
+            Some("gif") => "image/gif",
+            Some("webp") => "image/webp",
+            _ => {
+                warn!("Skipping cover {} of unknown type", cover_path.display());
+                continue;
+            }
+        };
+        let bytes = match fs::read(&cover_path) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                warn!("Can't read cover {}: {}", cover_path.display(), e);
+                continue;
+            }
+        };
+        let class = format!("cover-{}", classes.len());
+        writeln!(
+            css,
+            ".{} {{ background-image: url(data:{};base64,{}); }}",
+            class,
+            mime,
+            base64(&bytes)
+        )
+        .unwrap();
+        classes.insert(l.id.as_str(), class);
+    }
+    classes
+}
+
+fn summary_card(html: &mut String, summary: &Summary) {
+    write!(
+        html,
+        "<div class=\"card\"><h2>{}</h2><dl>",
+        escape(&summary.player)
+    )
+    .unwrap();
+    let mut item = |name: &str, value: String| {
+        write!(html, "<dt>{}</dt><dd>{}</dd>", name, escape(&value)).unwrap();
+    };
+    item(
+        "Levels played",
+        format!("{} of {}", summary.levels_played, summary.levels),
+    );
+    item(
+        "Difficulties played",
+        format!(
+            "{} of {}",
+            summary.difficulties_played, summary.difficulties
+        ),
+    );
+    item("Plays", summary.plays.to_string());
+    item("Full combos", summary.full_combos.to_string());
+    item(
+        "Valid scores",
+        format!(
+            "{} ({} invalid)",
+            summary.valid_scores, summary.invalid_scores
+        ),
+    );
+    item(
+        "Ranks",
+        summary
+            .ranks
+            .iter()
+            .filter(|r| r.count > 0)
+            .map(|r| format!("{} {}", r.rank, r.count))
+            .collect::<Vec<_>>()
+            .join(", "),
+    );
+    html.push_str("</dl></div>\n");
+}
+
+/// An inline SVG line of the notes per second in each timeline bucket, scaled to `max_nps`.
+fn sparkline(nps: &[f64], max_nps: f64) -> String {
+    let step = SPARKLINE_WIDTH / (nps.len().max(2) - 1) as f64;
+    let points = nps
+        .iter()
+        .enumerate()
+        .map(|(i, n)| {
+            format!(
+                "{:.1},{:.1}",
+                i as f64 * step,
+                SPARKLINE_HEIGHT * (1.0 - n / max_nps)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "<svg width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\"><polyline points=\"{}\"/></svg>",
+        points,
+        w = SPARKLINE_WIDTH,
+        h = SPARKLINE_HEIGHT
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
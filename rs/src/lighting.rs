@@ -0,0 +1,96 @@
+use crate::density::{round2, TempoMap};
+use serde::Serialize;
+use serde_json::Value;
+
+// v2 event types that aren't lighting
+const V2_COLOR_BOOST: u64 = 5;
+const V2_NON_LIGHTING: [u64; 3] = [14, 15, 100]; // rotations and BPM changes
+
+// legacy Chroma stored RGB in the event value, offset by this
+const LEGACY_CHROMA_RGB: u64 = 2_000_000_000;
+
+#[derive(Serialize, Clone)]
+pub struct LightingStats {
+    /// Lighting events, including color boosts and v3 event box groups.
+    pub events: u32,
+    /// Events over the time between the first and last event.
+    pub events_per_second: f64,
+    pub color_boosts: u32,
+    /// Uses v3 light groups (event box groups).
+    pub group_lighting: bool,
+    /// Uses Chroma custom colors on events.
+    pub chroma_rgb: bool,
+}
+
+impl LightingStats {
+    /// Reads lighting from a v2 or v3 difficulty file.
+    pub fn new(difficulty_file: &Value, tempo_map: &TempoMap) -> LightingStats {
+        let mut beats = Vec::new();
+        let mut color_boosts = 0;
+        let mut chroma_rgb = false;
+        let mut group_lighting = false;
+
+        if let Some(events) = difficulty_file["_events"].as_array() {
+            for e in events {
+                let event_type = e["_type"].as_u64().unwrap_or(0);
+                if V2_NON_LIGHTING.contains(&event_type) {
+                    continue;
+                }
+                if event_type == V2_COLOR_BOOST {
+                    color_boosts += 1;
+                }
+                chroma_rgb |= !e["_customData"]["_color"].is_null()
+                    || !e["_customData"]["_lightGradient"].is_null()
+                    || e["_value"].as_u64().unwrap_or(0) >= LEGACY_CHROMA_RGB;
+                beats.extend(e["_time"].as_f64());
+            }
+        }
+        for e in difficulty_file["basicBeatmapEvents"]
+            .as_array()
+            .into_iter()
+            .flatten()
+        {
+            chroma_rgb |= !e["customData"]["color"].is_null();
+            beats.extend(e["b"].as_f64());
+        }
+        for e in difficulty_file["colorBoostBeatmapEvents"]
+            .as_array()
+            .into_iter()
+            .flatten()
+        {
+            color_boosts += 1;
+            beats.extend(e["b"].as_f64());
+        }
+        for key in [
+            "lightColorEventBoxGroups",
+            "lightRotationEventBoxGroups",
+            "lightTranslationEventBoxGroups",
+            "vfxEventBoxGroups",
+        ] {
+            for g in difficulty_file[key].as_array().into_iter().flatten() {
+                group_lighting = true;
+                beats.extend(g["b"].as_f64());
+            }
+        }
+
+        let (first, last) = beats.iter().fold((f64::MAX, f64::MIN), |(first, last), b| {
+            (first.min(*b), last.max(*b))
+        });
+        let seconds = if beats.len() > 1 {
+            tempo_map.seconds(last) - tempo_map.seconds(first)
+        } else {
+            0.0
+        };
+        LightingStats {
+            events: beats.len() as u32,
+            events_per_second: if seconds > 0.0 {
+                round2(beats.len() as f64 / seconds)
+            } else {
+                0.0
+            },
+            color_boosts,
+            group_lighting,
+            chroma_rgb,
+        }
+    }
+}
@@ -0,0 +1,479 @@
+use crate::beatmap::{Beatmap, NoteKind};
+use crate::custom_data;
+use clap::ArgMatches;
+use hex::ToHex;
+use log::debug;
+use serde::Serialize;
+use serde_json::Value;
+use sha1::{Digest, Sha1};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+const VALID_DIFFICULTY_RANKS: [u64; 5] = [1, 3, 5, 7, 9];
+// mods that allow notes outside the 4x3 grid
+const OFF_GRID_MODS: [&str; 2] = ["Mapping Extensions", "Noodle Extensions"];
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    InvalidJson,
+    MissingFile,
+    FilenameCase,
+    HashCase,
+    UnknownDifficultyRank,
+    UnknownVersion,
+    InvalidBeatmap,
+    NoteOutsideGrid,
+    NoteBeforeStart,
+    NoteAfterEnd,
+    OverlappingNotes,
+    ZeroDurationWall,
+    Unsorted,
+}
+
+#[derive(Serialize)]
+pub struct Issue {
+    pub kind: IssueKind,
+    /// File in the level folder the issue is in.
+    pub file: String,
+    pub beat: Option<f64>,
+    pub message: String,
+}
+
+#[derive(Serialize)]
+pub struct LevelReport {
+    pub folder: String,
+    pub issues: Vec<Issue>,
+}
+
+/// Checks every custom level for structural problems, then prints a report and exits with 1 if any
+/// were found.
+pub fn run(custom_levels_path: &Path, save_path: &Path, args: &ArgMatches) {
+    let level_ids = if save_path.is_file() {
+        let player_data: Value =
+            serde_json::from_reader(fs::File::open(save_path).unwrap()).unwrap();
+        saved_level_ids(&player_data)
+    } else {
+        debug!("No save file, skipping the level hash case check");
+        HashMap::new()
+    };
+
+    let mut level_dirs = custom_levels_path
+        .read_dir()
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|p| p.join("info.dat").is_file())
+        .collect::<Vec<_>>();
+    level_dirs.sort();
+    let reports = level_dirs
+        .iter()
+        .map(|dir| LevelReport {
+            folder: dir.file_name().unwrap().to_string_lossy().into_owned(),
+            issues: lint_level(dir, &level_ids),
+        })
+        .filter(|r| !r.issues.is_empty())
+        .collect::<Vec<_>>();
+
+    if args.is_present("json") {
+        println!("{}", serde_json::to_string_pretty(&reports).unwrap());
+    } else {
+        for report in &reports {
+            println!("{}", report.folder);
+            for issue in &report.issues {
+                match issue.beat {
+                    Some(beat) => println!("  {} at beat {}: {}", issue.file, beat, issue.message),
+                    None => println!("  {}: {}", issue.file, issue.message),
+                }
+            }
+        }
+        println!(
+            "{} issues in {} of {} levels",
+            reports.iter().map(|r| r.issues.len()).sum::<usize>(),
+            reports.len(),
+            level_dirs.len()
+        );
+    }
+    if !reports.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+/// The level IDs with scores for any local or guest player, grouped by their uppercase form. Scores
+/// are only needed for the hash check.
+fn saved_level_ids(player_data: &Value) -> HashMap<String, HashSet<String>> {
+    let mut level_ids = HashMap::<String, HashSet<String>>::new();
+    for id in ["localPlayers", "guestPlayers"]
+        .iter()
+        .flat_map(|key| player_data[key].as_array().into_iter().flatten())
+        .flat_map(|p| p["levelsStatsData"].as_array().into_iter().flatten())
+        .filter_map(|s| s["levelId"].as_str())
+    {
+        level_ids
+            .entry(id.to_ascii_uppercase())
+            .or_default()
+            .insert(id.to_owned());
+    }
+    level_ids
+}
+
+/// Scores saved under another case of `level_id`, which the game doesn't match to the level.
+fn hash_case_issues(level_id: &str, level_ids: &HashMap<String, HashSet<String>>) -> Vec<Issue> {
+    let mut saved_ids = level_ids
+        .get(&level_id.to_ascii_uppercase())
+        .into_iter()
+        .flatten()
+        .filter(|saved_id| *saved_id != level_id)
+        .collect::<Vec<_>>();
+    saved_ids.sort();
+    saved_ids
+        .into_iter()
+        .map(|saved_id| Issue {
+            kind: IssueKind::HashCase,
+            file: "info.dat".to_owned(),
+            beat: None,
+            message: format!(
+                "scores are saved under {} but the level hash is {}",
+                saved_id, level_id
+            ),
+        })
+        .collect()
+}
+
+fn lint_level(dir: &Path, level_ids: &HashMap<String, HashSet<String>>) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let file_names = dir
+        .read_dir()
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    let mut hasher = Sha1::new();
+    let level_info_src = match read_json(dir, "info.dat", &mut hasher, &mut issues) {
+        Some(info) => info,
+        None => return issues,
+    };
+    // v2 info files have `_version`, v4 has `version`
+    if let Some(version) = level_info_src["version"].as_str() {
+        issues.push(Issue {
+            kind: IssueKind::UnknownVersion,
+            file: "info.dat".to_owned(),
+            beat: None,
+            message: format!("unsupported info.dat version {}", version),
+        });
+        return issues;
+    }
+    let bpm = level_info_src["_beatsPerMinute"].as_f64().unwrap_or(0.0);
+
+    for key in ["_songFilename", "_coverImageFilename"] {
+        if let Some(file) = level_info_src[key].as_str() {
+            check_file_exists(file, &file_names, "info.dat", &mut issues);
+        }
+    }
+    let song_seconds = level_info_src["_songFilename"]
+        .as_str()
+        .and_then(|f| ogg_duration(&dir.join(f)));
+
+    let mut all_files_hashed = true;
+    for characteristic in level_info_src["_difficultyBeatmapSets"]
+        .as_array()
+        .into_iter()
+        .flatten()
+    {
+        let characteristic_name = characteristic["_beatmapCharacteristicName"]
+            .as_str()
+            .unwrap_or("");
+        for difficulty_info in characteristic["_difficultyBeatmaps"]
+            .as_array()
+            .into_iter()
+            .flatten()
+        {
+            let rank = difficulty_info["_difficultyRank"].as_u64();
+            if !rank.is_some_and(|r| VALID_DIFFICULTY_RANKS.contains(&r)) {
+                issues.push(Issue {
+                    kind: IssueKind::UnknownDifficultyRank,
+                    file: "info.dat".to_owned(),
+                    beat: None,
+                    message: format!(
+                        "{} difficulty has _difficultyRank {}, expected one of {:?}",
+                        characteristic_name,
+                        difficulty_info["_difficultyRank"],
+                        VALID_DIFFICULTY_RANKS
+                    ),
+                });
+            }
+            let file = match difficulty_info["_beatmapFilename"].as_str() {
+                Some(f) => f,
+                None => continue,
+            };
+            if !check_file_exists(file, &file_names, "info.dat", &mut issues) {
+                all_files_hashed = false;
+                continue;
+            }
+            let difficulty_file = match read_json(dir, file, &mut hasher, &mut issues) {
+                Some(d) => d,
+                None => {
+                    all_files_hashed = false;
+                    continue;
+                }
+            };
+            let requirements = custom_data::string_list(difficulty_info, "requirements");
+            let off_grid_allowed = requirements
+                .iter()
+                .any(|r| OFF_GRID_MODS.contains(&r.as_str()));
+            lint_difficulty(
+                file,
+                &difficulty_file,
+                bpm,
+                song_seconds,
+                off_grid_allowed,
+                &mut issues,
+            );
+        }
+    }
+
+    if all_files_hashed {
+        let level_id =
+            "custom_level_".to_owned() + &(hasher.finalize().encode_hex_upper::<String>());
+        issues.extend(hash_case_issues(&level_id, level_ids));
+    }
+    issues
+}
+
+fn lint_difficulty(
+    file: &str,
+    difficulty_file: &Value,
+    bpm: f64,
+    song_seconds: Option<f64>,
+    off_grid_allowed: bool,
+    issues: &mut Vec<Issue>,
+) {
+    let mut issue = |kind, beat, message| {
+        issues.push(Issue {
+            kind,
+            file: file.to_owned(),
+            beat,
+            message,
+        })
+    };
+    let version = difficulty_file["version"].as_str().unwrap_or("2.0.0");
+    if !version.starts_with('2') && !version.starts_with('3') {
+        issue(
+            IssueKind::UnknownVersion,
+            None,
+            format!("unsupported schema version {}", version),
+        );
+        return;
+    }
+
+    for (key, time_key) in [
+        ("_notes", "_time"),
+        ("_obstacles", "_time"),
+        ("_events", "_time"),
+        ("colorNotes", "b"),
+        ("bombNotes", "b"),
+        ("obstacles", "b"),
+        ("sliders", "b"),
+        ("burstSliders", "b"),
+        ("basicBeatmapEvents", "b"),
+    ] {
+        let times = difficulty_file[key]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|o| o[time_key].as_f64().unwrap_or(0.0))
+            .collect::<Vec<_>>();
+        if let Some(i) = (1..times.len()).find(|i| times[*i] < times[i - 1]) {
+            issue(
+                IssueKind::Unsorted,
+                Some(times[i]),
+                format!("{} is not sorted by time", key),
+            );
+        }
+    }
+
+    let beatmap = match Beatmap::parse(difficulty_file, bpm) {
+        Ok(beatmap) => beatmap,
+        Err(e) => {
+            issue(IssueKind::InvalidBeatmap, None, e);
+            return;
+        }
+    };
+    let mut cells = HashSet::new();
+    for note in &beatmap.notes {
+        if note.beat < 0.0 {
+            issue(
+                IssueKind::NoteBeforeStart,
+                Some(note.beat),
+                "note before the start of the song".to_owned(),
+            );
+        }
+        if let Some(song_seconds) = song_seconds {
+            if beatmap.tempo_map.seconds(note.beat) > song_seconds {
+                issue(
+                    IssueKind::NoteAfterEnd,
+                    Some(note.beat),
+                    format!("note after the end of the song at {:.2}s", song_seconds),
+                );
+            }
+        }
+        if !off_grid_allowed && (!(0..=3).contains(&note.x) || !(0..=2).contains(&note.y)) {
+            issue(
+                IssueKind::NoteOutsideGrid,
+                Some(note.beat),
+                format!("note outside the grid at x={} y={}", note.x, note.y),
+            );
+        }
+        // arc ends sit on notes by design
+        if matches!(note.kind, NoteKind::Color | NoteKind::Bomb)
+            && !cells.insert(((note.beat * 1000.0).round() as i64, note.x, note.y))
+        {
+            issue(
+                IssueKind::OverlappingNotes,
+                Some(note.beat),
+                format!("more than one note at x={} y={}", note.x, note.y),
+            );
+        }
+    }
+    for obstacle in &beatmap.obstacles {
+        if obstacle.duration <= 0.0 {
+            issue(
+                IssueKind::ZeroDurationWall,
+                Some(obstacle.beat),
+                format!("wall with duration {}", obstacle.duration),
+            );
+        }
+    }
+}
+
+/// Checks a file referenced by `referenced_in` exists with exactly that name, which matters on
+/// case-sensitive file systems.
+fn check_file_exists(
+    file: &str,
+    file_names: &[String],
+    referenced_in: &str,
+    issues: &mut Vec<Issue>,
+) -> bool {
+    if file_names.iter().any(|f| f == file) {
+        return true;
+    }
+    match file_names.iter().find(|f| f.eq_ignore_ascii_case(file)) {
+        Some(actual) => {
+            issues.push(Issue {
+                kind: IssueKind::FilenameCase,
+                file: referenced_in.to_owned(),
+                beat: None,
+                message: format!("references {} but the file is named {}", file, actual),
+            });
+            true
+        }
+        None => {
+            issues.push(Issue {
+                kind: IssueKind::MissingFile,
+                file: referenced_in.to_owned(),
+                beat: None,
+                message: format!("references missing file {}", file),
+            });
+            false
+        }
+    }
+}
+
+fn read_json(dir: &Path, file: &str, hasher: &mut Sha1, issues: &mut Vec<Issue>) -> Option<Value> {
+    // the case check has already been reported, so open whichever file matches
+    let path = dir
+        .read_dir()
+        .ok()?
+        .map(|entry| entry.unwrap().path())
+        .find(|p| {
+            p.file_name()
+                .unwrap()
+                .to_string_lossy()
+                .eq_ignore_ascii_case(file)
+        })?;
+    let mut bytes = Vec::new();
+    fs::File::open(path).ok()?.read_to_end(&mut bytes).ok()?;
+    hasher.update(&bytes);
+    match serde_json::from_slice(&bytes) {
+        Ok(json) => Some(json),
+        Err(e) => {
+            issues.push(Issue {
+                kind: IssueKind::InvalidJson,
+                file: file.to_owned(),
+                beat: None,
+                message: e.to_string(),
+            });
+            None
+        }
+    }
+}
+
+/// Length in seconds of an Ogg Vorbis file (`.egg` or `.ogg`), from the last page's granule position.
+fn ogg_duration(path: &Path) -> Option<f64> {
+    let mut f = fs::File::open(path).ok()?;
+    let mut head = [0u8; 4096];
+    let head_len = f.read(&mut head).ok()?;
+    let id_header = head[..head_len]
+        .windows(7)
+        .position(|w| w == b"\x01vorbis")?;
+    // identification header: type, "vorbis", version (4), channels (1), sample rate (4)
+    let rate_start = id_header + 12;
+    let sample_rate = u32::from_le_bytes(head.get(rate_start..rate_start + 4)?.try_into().ok()?);
+
+    let len = f.seek(SeekFrom::End(0)).ok()?;
+    let tail_len = len.min(65536);
+    f.seek(SeekFrom::Start(len - tail_len)).ok()?;
+    let mut tail = Vec::with_capacity(tail_len as usize);
+    f.read_to_end(&mut tail).ok()?;
+    let last_page = tail.windows(4).rposition(|w| w == b"OggS")?;
+    // page header: "OggS", version (1), type (1), granule position (8)
+    let granule = i64::from_le_bytes(tail.get(last_page + 6..last_page + 14)?.try_into().ok()?);
+    if sample_rate == 0 || granule < 0 {
+        return None;
+    }
+    Some(granule as f64 / sample_rate as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const LEVEL_ID: &str = "custom_level_0A1B2C";
+
+    fn save(local_ids: &[&str], guest_ids: &[&str]) -> Value {
+        let player = |ids: &[&str]| {
+            json!([{
+                "levelsStatsData": ids.iter().map(|id| json!({"levelId": id})).collect::<Vec<_>>()
+            }])
+        };
+        json!({"localPlayers": player(local_ids), "guestPlayers": player(guest_ids)})
+    }
+
+    #[test]
+    fn lowercase_hash_is_a_hash_case_issue() {
+        let level_ids = saved_level_ids(&save(&["custom_level_0a1b2c"], &[]));
+        let issues = hash_case_issues(LEVEL_ID, &level_ids);
+        assert_eq!(issues.len(), 1);
+        assert!(matches!(issues[0].kind, IssueKind::HashCase));
+        assert_eq!(
+            issues[0].message,
+            "scores are saved under custom_level_0a1b2c but the level hash is custom_level_0A1B2C"
+        );
+    }
+
+    #[test]
+    fn matching_hash_is_not_an_issue() {
+        let level_ids = saved_level_ids(&save(&[LEVEL_ID], &[]));
+        assert!(hash_case_issues(LEVEL_ID, &level_ids).is_empty());
+        assert!(hash_case_issues("custom_level_FFFF", &level_ids).is_empty());
+    }
+
+    #[test]
+    fn guest_scores_are_checked() {
+        let level_ids = saved_level_ids(&save(&[LEVEL_ID], &["custom_level_0a1B2c"]));
+        let issues = hash_case_issues(LEVEL_ID, &level_ids);
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].message.contains("custom_level_0a1B2c"));
+    }
+}
@@ -1,6 +1,40 @@
-use clap::{App, Arg};
+mod beatmap;
+mod beatsaver;
+mod custom_data;
+mod density;
+mod duplicates;
+mod filter;
+#[cfg(test)]
+mod fixtures;
+mod groups;
+mod html;
+mod lighting;
+mod lint;
+mod mods;
+mod patterns;
+mod players;
+mod playlist;
+mod rating;
+mod recommend;
+mod rotation;
+mod score;
+mod serve;
+mod sort;
+mod summary;
+mod timeline;
+mod tui;
+mod watch;
+
+use beatmap::Beatmap;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use custom_data::Contributor;
+use density::PeakDensity;
 use hex::ToHex;
+use lighting::LightingStats;
 use log::{debug, warn};
+use patterns::PatternStats;
+use rating::Rating;
+use rotation::RotationStats;
 use serde::Serialize;
 use serde_json::Value;
 use sha1::{Digest, Sha1};
@@ -12,27 +46,254 @@ use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::thread;
+use timeline::Timeline;
 
 fn main() {
     let args = App::new("Beat Saber Stats")
         .arg(
             Arg::with_name("save-path")
                 .long("save-path")
-                .takes_value(true),
+                .takes_value(true)
+                .global(true),
         )
         .arg(
             Arg::with_name("game-path")
                 .long("game-path")
-                .takes_value(true),
+                .takes_value(true)
+                .global(true),
         )
         .arg(
             Arg::with_name("player-number")
                 .long("player-number")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("all-players")
+                .long("all-players")
+                .conflicts_with("player-number")
+                .help("Output the scores of every local and guest player, with a Player column"),
+        )
         .arg(Arg::with_name("threads").long("threads").takes_value(true))
+        .arg(
+            Arg::with_name("windows")
+                .long("windows")
+                .takes_value(true)
+                .help("Comma-separated peak density windows in seconds, output as NP{n}S columns")
+                .default_value("10"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&["csv", "html"])
+                .help("Write stats.csv, or a self-contained stats.html report")
+                .default_value("csv"),
+        )
+        .arg(
+            Arg::with_name("timeline")
+                .long("timeline")
+                .takes_value(true)
+                .help("Also write a density timeline per difficulty to this file (.json or .csv)"),
+        )
+        .arg(
+            Arg::with_name("timeline-bucket")
+                .long("timeline-bucket")
+                .takes_value(true)
+                .help("Length in seconds of each timeline bucket")
+                .default_value("1"),
+        )
+        .arg(
+            Arg::with_name("require-playable-with")
+                .long("require-playable-with")
+                .takes_value(true)
+                .help("Only output difficulties whose required mods are all in this comma-separated list (empty for vanilla)"),
+        )
+        .arg(
+            Arg::with_name("beatsaver-dump")
+                .long("beatsaver-dump")
+                .takes_value(true)
+                .help("BeatSaver metadata dump (JSON) to fill in song details of deleted custom levels"),
+        )
+        .arg(
+            Arg::with_name("filter")
+                .long("filter")
+                .takes_value(true)
+                .help("Only output difficulties matching this expression, e.g. 'np10s >= 8 && rank < \"S\"'"),
+        )
+        .arg(
+            Arg::with_name("sort")
+                .long("sort")
+                .takes_value(true)
+                .help("Comma-separated fields to sort by, each optionally followed by :asc or :desc, e.g. valid:desc,score:desc"),
+        )
+        .arg(
+            Arg::with_name("columns")
+                .long("columns")
+                .takes_value(true)
+                .help("Comma-separated columns to write, in order, e.g. Song,Difficulty,NP10S,Score"),
+        )
+        .arg(
+            Arg::with_name("playlist")
+                .long("playlist")
+                .takes_value(true)
+                .help("Also write the output levels and difficulties to this .bplist playlist"),
+        )
+        .arg(
+            Arg::with_name("playlist-title")
+                .long("playlist-title")
+                .takes_value(true)
+                .requires("playlist")
+                .help("Playlist title, the file name by default"),
+        )
+        .arg(
+            Arg::with_name("playlist-author")
+                .long("playlist-author")
+                .takes_value(true)
+                .default_value("Beat Saber Stats"),
+        )
+        .arg(
+            Arg::with_name("playlist-cover")
+                .long("playlist-cover")
+                .takes_value(true)
+                .requires("playlist")
+                .help("Image file to use as the playlist cover"),
+        )
+        .arg(
+            Arg::with_name("metadata")
+                .long("metadata")
+                .help("Output contributors, colors, warnings and information from _customData"),
+        )
+        .arg(
+            Arg::with_name("lighting")
+                .long("lighting")
+                .help("Output lighting event statistics and whether each difficulty is a lightshow (v2 and v3)"),
+        )
+        .arg(
+            Arg::with_name("rotation")
+                .long("rotation")
+                .help("Output rotation statistics for 90 and 360 degree difficulties (v2 and v3)"),
+        )
+        .arg(
+            Arg::with_name("patterns")
+                .long("patterns")
+                .help("Output the percentage of notes in each pattern (streams, jumps, etc.)"),
+        )
         // TODO OutFile and OutMode
-        .arg(Arg::with_name("v").short("v"))
+        .arg(Arg::with_name("v").short("v").global(true))
+        .subcommand(
+            SubCommand::with_name("lint")
+                .about("Report structural problems in custom levels instead of writing stats")
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("Print the report as JSON"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("profile")
+                .about("Print campaign progress, favorites, modifiers, settings and overall stats")
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("Print the players as JSON"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("summary")
+                .about("Print totals, ranks and completion per difficulty and characteristic instead of writing stats")
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("Print the summary as JSON"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("groups")
+                .about("Print maps, NP10S, accuracy, plays and FC rate per mapper, artist or environment as CSV, or write groups.html with --format html")
+                .arg(
+                    Arg::with_name("by")
+                        .required(true)
+                        .possible_values(&groups::GROUP_FIELDS),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("Print the groups as JSON"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("playlists")
+                .about("Print progress through each playlist in the game's Playlists folder")
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("Print the progress as JSON"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("recommend")
+                .about("Suggest unplayed or weakly scored difficulties just above the NP10S you're comfortable at")
+                .arg(
+                    Arg::with_name("stretch")
+                        .long("stretch")
+                        .takes_value(true)
+                        .help("How far above the comfortable NP10S to suggest, in percent")
+                        .default_value("20"),
+                )
+                .arg(
+                    Arg::with_name("count")
+                        .long("count")
+                        .takes_value(true)
+                        .help("Most suggestions per player")
+                        .default_value("20"),
+                )
+                .arg(
+                    Arg::with_name("csv")
+                        .long("csv")
+                        .takes_value(true)
+                        .help("Also write the suggestions to this CSV file"),
+                )
+                .arg(
+                    Arg::with_name("playlist")
+                        .long("playlist")
+                        .takes_value(true)
+                        .help("Also write the suggestions to this .bplist playlist"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("serve")
+                .about("Serve the stats as JSON over HTTP on localhost, rescanning in the background")
+                .arg(
+                    Arg::with_name("port")
+                        .long("port")
+                        .takes_value(true)
+                        .default_value("8080"),
+                )
+                .arg(
+                    Arg::with_name("rescan-interval")
+                        .long("rescan-interval")
+                        .takes_value(true)
+                        .help("Seconds between rescans of the levels and save file")
+                        .default_value("300"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("watch").about(
+                "Write the stats, then rewrite them whenever a level or the save file changes",
+            ),
+        )
+        .subcommand(SubCommand::with_name("tui").about(
+            "Browse the levels, their difficulties and density timelines in the terminal",
+        ))
+        .subcommand(
+            SubCommand::with_name("duplicates")
+                .about("Report custom levels that are copies or other versions of each other")
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("Print the report as JSON"),
+                ),
+        )
         .get_matches();
 
     env_logger::builder()
@@ -53,9 +314,6 @@ fn main() {
         };
     }
     let save_path: &Path = Path::new(&save_path);
-    if !save_path.is_file() {
-        panic!("Save file not found at {}", save_path.display());
-    }
     let mut game_path: String = args.value_of("game-path").unwrap_or("").to_owned();
     if game_path.is_empty() {
         if cfg!(windows) {
@@ -73,6 +331,14 @@ fn main() {
     if !levels_path.is_dir() {
         panic!("Game levels not found at {}", levels_path.display())
     }
+    let custom_levels_path = levels_path.join("CustomLevels");
+    if let Some(lint_args) = args.subcommand_matches("lint") {
+        lint::run(&custom_levels_path, save_path, lint_args);
+        return;
+    }
+    if !save_path.is_file() {
+        panic!("Save file not found at {}", save_path.display());
+    }
     let mut threads = args
         .value_of("threads")
         .unwrap_or("0")
@@ -81,26 +347,158 @@ fn main() {
     if threads == 0 {
         threads = num_cpus::get();
     }
+    let windows = density::parse_windows(args.value_of("windows").unwrap());
+    let filter = args.value_of("filter").map(|f| {
+        filter::parse(f, &windows).unwrap_or_else(|e| panic!("Invalid --filter {:?}: {}", f, e))
+    });
+    let sort_keys = args
+        .value_of("sort")
+        .map(|keys| {
+            sort::parse_sort_keys(keys, &windows)
+                .unwrap_or_else(|e| panic!("Invalid --sort: {}", e))
+        })
+        .unwrap_or_default();
+    if let Some(columns) = args.value_of("columns") {
+        select_columns(&output_header(&args, &windows), columns);
+    }
+    // the HTML report draws the timelines as sparklines, and the TUI as charts
+    let needs_timeline = args.is_present("timeline")
+        || args.value_of("format") == Some("html")
+        || args.subcommand_matches("tui").is_some();
+    let analysis_options = AnalysisOptions {
+        windows,
+        timeline_bucket: needs_timeline
+            .then(|| timeline::parse_bucket(args.value_of("timeline-bucket").unwrap())),
+    };
+    // recommendations are based on NP10S
+    if args.subcommand_matches("recommend").is_some() && !analysis_options.windows.contains(&10.0) {
+        panic!(
+            "recommend needs 10 in --windows, got {}",
+            args.value_of("windows").unwrap()
+        );
+    }
 
-    let player_data;
-    {
-        let _player_data: Value =
-            serde_json::from_reader(fs::File::open(save_path).unwrap()).unwrap();
-        let _player_data = _player_data["localPlayers"].as_array().unwrap();
-        let player_number = args
-            .value_of("player-number")
-            .unwrap_or("0")
-            .parse::<usize>()
-            .unwrap();
-        if _player_data.len() < player_number + 1 {
-            panic!("No players found in the save file");
-        }
-        player_data = _player_data[player_number].as_object().unwrap().clone();
+    let players = load_players(save_path, &args);
+    if let Some(profile_args) = args.subcommand_matches("profile") {
+        players::run(&players, profile_args);
+        return;
     }
     //endregion
 
-    //region constants
-    let custom_levels_path = levels_path.join("CustomLevels");
+    let analysis_options = Arc::new(analysis_options);
+    let beatsaver_dump = args
+        .value_of("beatsaver-dump")
+        .map(|p| beatsaver::load(Path::new(p)));
+    // rereads the save, so scores are always current
+    let stats_for = |scanned_levels: &[LevelInfo]| {
+        let players = load_players(save_path, &args);
+        let mut level_stats = player_level_stats(scanned_levels, &players, beatsaver_dump.as_ref());
+        apply_filters(&mut level_stats, &args, filter.as_ref());
+        (players, level_stats)
+    };
+    if let Some(serve_args) = args.subcommand_matches("serve") {
+        serve::run(serve_args, || {
+            stats_for(&scan_custom_levels(
+                &custom_levels_path,
+                threads,
+                &analysis_options,
+            ))
+        });
+        return;
+    }
+    if args.subcommand_matches("watch").is_some() {
+        watch::run(
+            &custom_levels_path,
+            save_path,
+            scan_custom_levels(&custom_levels_path, threads, &analysis_options),
+            &analysis_options,
+            |scanned_levels| {
+                let level_stats = stats_for(scanned_levels).1;
+                write_outputs(
+                    &level_stats,
+                    &args,
+                    &analysis_options.windows,
+                    &sort_keys,
+                    &custom_levels_path,
+                );
+            },
+        );
+        return;
+    }
+
+    let scanned_levels = scan_custom_levels(&custom_levels_path, threads, &analysis_options);
+    if let Some(duplicates_args) = args.subcommand_matches("duplicates") {
+        let level_stats = player_level_stats(&scanned_levels, &players, None);
+        duplicates::run(&level_stats, duplicates_args);
+        return;
+    }
+
+    let mut level_stats = player_level_stats(&scanned_levels, &players, beatsaver_dump.as_ref());
+
+    if let Some(playlists_args) = args.subcommand_matches("playlists") {
+        playlist::run(
+            &game_path.join("Playlists"),
+            &level_stats,
+            &players,
+            playlists_args,
+        );
+        return;
+    }
+
+    apply_filters(&mut level_stats, &args, filter.as_ref());
+
+    if let Some(summary_args) = args.subcommand_matches("summary") {
+        summary::run(&level_stats, &players, summary_args);
+        return;
+    }
+    if args.subcommand_matches("tui").is_some() {
+        tui::run(&level_stats, &analysis_options.windows);
+        return;
+    }
+    if let Some(groups_args) = args.subcommand_matches("groups") {
+        groups::run(&level_stats, &args, groups_args);
+        return;
+    }
+    if let Some(recommend_args) = args.subcommand_matches("recommend") {
+        recommend::run(
+            &level_stats,
+            &players,
+            recommend_args,
+            args.value_of("playlist-author").unwrap(),
+        );
+        return;
+    }
+
+    write_outputs(
+        &level_stats,
+        &args,
+        &analysis_options.windows,
+        &sort_keys,
+        &custom_levels_path,
+    );
+}
+
+const DIFFICULTY_NAME_MAP: [&str; 5] = ["Easy", "Normal", "Hard", "Expert", "Expert+"];
+const SCORE_RANK_MAP: [&str; 8] = ["E", "D", "C", "B", "A", "S", "SS", "SSS"];
+
+/// Reads the players selected by `--player-number` or `--all-players` from the save file.
+fn load_players(save_path: &Path, args: &ArgMatches) -> Vec<players::Player> {
+    let save_data: players::SaveData =
+        serde_json::from_reader(fs::File::open(save_path).unwrap()).unwrap();
+    let player_number = args
+        .value_of("player-number")
+        .unwrap_or("0")
+        .parse::<usize>()
+        .unwrap();
+    players::select(save_data, args.is_present("all-players"), player_number)
+}
+
+/// Processes every custom level folder on `threads` threads, without scores.
+fn scan_custom_levels(
+    custom_levels_path: &Path,
+    threads: usize,
+    analysis_options: &Arc<AnalysisOptions>,
+) -> Vec<LevelInfo> {
     let custom_level_info_files = custom_levels_path
         .read_dir()
         .unwrap()
@@ -114,20 +512,18 @@ fn main() {
         })
         .collect::<VecDeque<_>>();
     let level_stats = Vec::<LevelInfo>::with_capacity(custom_level_info_files.len());
-    //endregion
 
     let level_info_queue = Arc::new(Mutex::new(custom_level_info_files));
     let level_stats_arc = Arc::new(Mutex::new(level_stats));
     let mut thread_handles = Vec::<std::thread::JoinHandle<()>>::with_capacity(threads);
-    let player_data = Arc::new(player_data);
     debug!("using threads={}", threads);
     for _i in 0..threads {
         let queue = Arc::clone(&level_info_queue);
         let mut stats = Arc::clone(&level_stats_arc);
-        let player_data = Arc::clone(&player_data);
+        let analysis_options = Arc::clone(analysis_options);
         thread_handles.push(thread::spawn(move || {
             // TODO Builder for thread name
-            process_queue(queue, &mut stats, player_data);
+            process_queue(queue, &mut stats, analysis_options);
         }));
     }
     for t in thread_handles {
@@ -135,20 +531,631 @@ fn main() {
     }
     // TODO Stopwatch on "Progress:"
     debug!("Progress: finished CustomLevels, starting OST");
+    let level_stats = std::mem::take(&mut *level_stats_arc.lock().unwrap());
+    level_stats
+}
+
+/// A copy of the scanned levels with each player's scores, plus OST levels and levels that only
+/// have scores, for every player in turn.
+fn player_level_stats(
+    scanned_levels: &[LevelInfo],
+    players: &[players::Player],
+    beatsaver_dump: Option<&HashMap<String, beatsaver::DumpLevel>>,
+) -> Vec<LevelInfo> {
+    let mut level_stats = Vec::<LevelInfo>::new();
+    for player in players {
+        debug!("Progress: adding scores for player={}", player.name);
+        let mut player_stats = scanned_levels.to_vec();
+        apply_scores(&mut player_stats, &player.scores);
+        add_unscanned_levels(&mut player_stats, &player.scores, beatsaver_dump);
+        // levels are scanned in parallel, so put them in a stable order, with missing levels last
+        player_stats.sort_by_cached_key(|l| {
+            (
+                l.song.is_empty(),
+                l.song.to_lowercase(),
+                l.artist.to_lowercase(),
+                l.mapper.to_lowercase(),
+                l.id.clone(),
+            )
+        });
+        for l in player_stats.iter_mut() {
+            l.player = player.name.clone();
+            l.player_index = player.index;
+            l.favorite = player.favorite_level_ids.contains(&l.id);
+        }
+        level_stats.extend(player_stats);
+    }
 
+    // the save keeps the best score and best rank separately, so they can disagree
+    for l in level_stats.iter() {
+        for c in &l.characteristics {
+            for d in &c.1.difficulties {
+                if let (Some(max_score), true) = (d.1.max_score, d.1.plays > 0) {
+                    let rank = SCORE_RANK_MAP[score::rank_for_score(d.1.score, max_score)];
+                    if rank != d.1.rank {
+                        warn!(
+                            "Rank mismatch for id={} char={} diff={} saved={} derived={}",
+                            l.id, c.0, d.0, d.1.rank, rank
+                        );
+                    }
+                }
+            }
+        }
+    }
+    level_stats
+}
+
+/// Removes the difficulties that don't match `--require-playable-with` or `--filter`, and levels
+/// left without any.
+fn apply_filters(
+    level_stats: &mut Vec<LevelInfo>,
+    args: &ArgMatches,
+    filter: Option<&filter::Expr>,
+) {
+    if let Some(installed) = args.value_of("require-playable-with") {
+        let installed = mods::parse_mods(installed);
+        for l in level_stats.iter_mut() {
+            for c in l.characteristics.values_mut() {
+                c.difficulties
+                    .retain(|_, d| mods::is_playable_with(&d.requirements, &installed));
+            }
+            l.characteristics.retain(|_, c| !c.difficulties.is_empty());
+        }
+        level_stats.retain(|l| !l.characteristics.is_empty());
+    }
+    if let Some(filter) = filter {
+        for l in level_stats.iter_mut() {
+            // taken out so each row can borrow the rest of the level
+            let mut characteristics = std::mem::take(&mut l.characteristics);
+            for (characteristic, c) in characteristics.iter_mut() {
+                c.difficulties.retain(|difficulty, d| {
+                    filter.matches(&filter::Row {
+                        level: l,
+                        characteristic,
+                        difficulty,
+                        stats: d,
+                    })
+                });
+            }
+            characteristics.retain(|_, c| !c.difficulties.is_empty());
+            l.characteristics = characteristics;
+        }
+        level_stats.retain(|l| !l.characteristics.is_empty());
+    }
+}
+
+// TODO output enhancements
+/// Writes stats.csv or stats.html, and the `--playlist` and `--timeline` files if given.
+fn write_outputs(
+    level_stats: &[LevelInfo],
+    args: &ArgMatches,
+    windows: &[f64],
+    sort_keys: &[sort::SortKey],
+    custom_levels_path: &Path,
+) {
+    debug!("Progress: finished orphans, starting output");
+    let all_players = args.is_present("all-players");
+    // the player with the highest score on each difficulty, to compare players
+    let mut best_players = HashMap::<(&str, &str, &str), (u32, &str)>::new();
+    if all_players {
+        for l in level_stats.iter() {
+            for c in &l.characteristics {
+                for d in c.1.difficulties.iter().filter(|d| d.1.plays > 0) {
+                    let best = best_players
+                        .entry((&l.id, c.0, d.0))
+                        .or_insert((d.1.score, &l.player));
+                    if d.1.score > best.0 {
+                        *best = (d.1.score, &l.player);
+                    }
+                }
+            }
+        }
+    }
+    let header = output_header(args, windows);
+    // indices into the full header of the columns to write
+    let columns = match args.value_of("columns") {
+        Some(names) => select_columns(&header, names),
+        None => (0..header.len()).collect(),
+    };
+    let mut rows = sort::rows(level_stats);
+    sort::sort_rows(&mut rows, sort_keys);
+    let mut records = Vec::with_capacity(rows.len());
+    for row in &rows {
+        let l = row.level;
+        let d = row.stats;
+        let mut record = vec![
+            l.song.clone(),
+            l.artist.clone(),
+            l.mapper.clone(),
+            l.bpm.to_string(),
+            l.environment.clone(),
+            format!(
+                "{:02}:{:02}",
+                (l.duration / 60.0).floor(),
+                (l.duration % 60.0).floor()
+            ),
+            row.characteristic.to_owned(),
+            row.difficulty.to_owned(),
+            d.label.clone().unwrap_or_else(|| row.difficulty.to_owned()),
+            {
+                if let Some(notes) = &d.notes {
+                    notes.to_string()
+                } else {
+                    "".to_owned()
+                }
+            },
+            {
+                if let Some(nps) = &d.nps {
+                    nps.to_string()
+                } else {
+                    "".to_owned()
+                }
+            },
+        ];
+        record.extend(windows.iter().map(|w| {
+            if let Some(peak) = d.peak_nps.iter().find(|p| p.window == *w) {
+                peak.nps.to_string()
+            } else {
+                "".to_owned()
+            }
+        }));
+        if let Some(rating) = &d.rating {
+            record.extend([rating.tech.to_string(), rating.speed.to_string()]);
+        } else {
+            record.extend(["".to_owned(), "".to_owned()]);
+        }
+        if args.is_present("patterns") {
+            if let Some(patterns) = &d.patterns {
+                record.extend(patterns.percentages().iter().map(|p| p.to_string()));
+            } else {
+                record.extend(patterns::PATTERN_HEADERS.iter().map(|_| "".to_owned()));
+            }
+        }
+        record.extend([d.requirements.join(", "), d.suggestions.join(", ")]);
+        if args.is_present("lighting") {
+            if let Some(lighting) = &d.lighting {
+                record.extend([
+                    lighting.events.to_string(),
+                    lighting.events_per_second.to_string(),
+                    lighting.color_boosts.to_string(),
+                    lighting.group_lighting.to_string(),
+                    lighting.chroma_rgb.to_string(),
+                ]);
+            } else {
+                record.extend((0..5).map(|_| "".to_owned()));
+            }
+            record.push(d.is_lightshow(row.characteristic).to_string());
+        }
+        if args.is_present("rotation") {
+            if let Some(rotation) = &d.rotation {
+                record.extend([
+                    rotation.events.to_string(),
+                    rotation.total.to_string(),
+                    rotation.max_continuous.to_string(),
+                    rotation.per_minute.to_string(),
+                ]);
+            } else {
+                record.extend((0..4).map(|_| "".to_owned()));
+            }
+        }
+        if args.is_present("metadata") {
+            record.extend([
+                l.contributors
+                    .iter()
+                    .map(|c| format!("{}: {}", c.role, c.name))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                d.color_left.clone().unwrap_or_default(),
+                d.color_right.clone().unwrap_or_default(),
+                d.warnings.join(", "),
+                d.information.join(", "),
+            ]);
+        }
+        record.extend([
+            d.score.to_string(),
+            {
+                if let Some(max_score) = &d.max_score {
+                    max_score.to_string()
+                } else {
+                    "".to_owned()
+                }
+            },
+            {
+                if let Some(accuracy) = d.accuracy() {
+                    accuracy.to_string()
+                } else {
+                    "".to_owned()
+                }
+            },
+            d.combo.clone(),
+            d.rank.clone(),
+            d.plays.to_string(),
+            d.valid.to_string(),
+            l.favorite.to_string(),
+        ]);
+        if all_players {
+            record.insert(0, l.player.clone());
+            record.push(
+                best_players
+                    .get(&(l.id.as_str(), row.characteristic, row.difficulty))
+                    .map(|best| best.1.to_owned())
+                    .unwrap_or_default(),
+            );
+        }
+        record.extend([l.id.clone(), l.missing_locally.to_string()]);
+        records.push(columns.iter().map(|&i| record[i].clone()).collect());
+    }
+    let header = columns
+        .iter()
+        .map(|&i| header[i].clone())
+        .collect::<Vec<_>>();
+    if args.value_of("format") == Some("html") {
+        html::write(
+            Path::new("stats.html"),
+            &header,
+            &rows,
+            &records,
+            level_stats,
+            custom_levels_path,
+        );
+    } else {
+        let out_file = Path::new("stats.csv");
+        if out_file.exists() {
+            fs::remove_file(out_file).unwrap();
+        }
+        let mut wtr = csv::Writer::from_path(out_file).unwrap();
+        wtr.write_record(&header).unwrap();
+        for record in &records {
+            wtr.write_record(record).unwrap();
+        }
+        wtr.flush().unwrap();
+    }
+    if let Some(playlist_path) = args.value_of("playlist").map(Path::new) {
+        let title = args.value_of("playlist-title").map_or_else(
+            || playlist_path.file_stem().unwrap().to_string_lossy(),
+            |t| t.into(),
+        );
+        let mut playlist =
+            playlist::Playlist::new(&rows, &title, args.value_of("playlist-author").unwrap());
+        playlist.image = args
+            .value_of("playlist-cover")
+            .map(|c| playlist::encode_image(Path::new(c)));
+        playlist.write(playlist_path);
+    }
+    if let Some(timeline_path) = args.value_of("timeline").map(Path::new) {
+        timeline::write(level_stats, timeline_path);
+    }
+}
+
+/// Every column of the stats CSV for the given options, in the default order.
+fn output_header(args: &ArgMatches, windows: &[f64]) -> Vec<String> {
+    let mut header = vec![
+        "Song".to_owned(),
+        "Artist".to_owned(),
+        "Mapper".to_owned(),
+        "BPM".to_owned(),
+        "Environment".to_owned(),
+        "~Duration".to_owned(),
+        "Characteristic".to_owned(),
+        "Difficulty".to_owned(),
+        "Label".to_owned(),
+        "Notes".to_owned(),
+        "~NPS".to_owned(),
+    ];
+    header.extend(windows.iter().map(|w| format!("NP{}S", w)));
+    header.extend(["Tech".to_owned(), "Speed".to_owned()]);
+    if args.is_present("patterns") {
+        header.extend(patterns::PATTERN_HEADERS.iter().map(|h| h.to_string()));
+    }
+    header.extend(
+        ["Requirements", "Suggestions"]
+            .iter()
+            .map(|h| h.to_string()),
+    );
+    if args.is_present("lighting") {
+        header.extend(
+            [
+                "Events",
+                "Events/s",
+                "Color Boosts",
+                "Group Lighting",
+                "Chroma RGB",
+                "Lightshow",
+            ]
+            .iter()
+            .map(|h| h.to_string()),
+        );
+    }
+    if args.is_present("rotation") {
+        header.extend(
+            [
+                "Rotations",
+                "Total Rotation",
+                "Max Continuous Rotation",
+                "Rotations/min",
+            ]
+            .iter()
+            .map(|h| h.to_string()),
+        );
+    }
+    if args.is_present("metadata") {
+        header.extend(
+            [
+                "Contributors",
+                "Left Color",
+                "Right Color",
+                "Warnings",
+                "Information",
+            ]
+            .iter()
+            .map(|h| h.to_string()),
+        );
+    }
+    header.extend(
+        [
+            "Score",
+            "Max Score",
+            "Accuracy %",
+            "Combo",
+            "Rank",
+            "Plays",
+            "Valid",
+            "Favorite",
+        ]
+        .iter()
+        .map(|h| h.to_string()),
+    );
+    if args.is_present("all-players") {
+        header.insert(0, "Player".to_owned());
+        header.push("Best Player".to_owned());
+    }
+    header.extend(["ID".to_owned(), "Missing Locally".to_owned()]);
+    header
+}
+
+/// Finds the comma-separated column names (case-insensitive) in the header.
+fn select_columns(header: &[String], names: &str) -> Vec<usize> {
+    names
+        .split(',')
+        .map(|name| {
+            header
+                .iter()
+                .position(|h| h.eq_ignore_ascii_case(name.trim()))
+                .unwrap_or_else(|| {
+                    panic!(
+                        "Unknown column {:?} in --columns, the columns are: {}",
+                        name.trim(),
+                        header.join(", ")
+                    )
+                })
+        })
+        .collect()
+}
+
+fn process_queue(
+    queue: Arc<Mutex<VecDeque<PathBuf>>>,
+    level_stats: &mut Arc<Mutex<Vec<LevelInfo>>>,
+    analysis_options: Arc<AnalysisOptions>,
+) {
+    loop {
+        let current_file;
+        {
+            let mut locked_queue = queue.lock().unwrap();
+            current_file = locked_queue.pop_front();
+        }
+        match current_file {
+            Some(cf) => process_single_song(cf, level_stats, &analysis_options),
+            None => return,
+        }
+    }
+}
+
+fn process_single_song(
+    level_info_file: PathBuf,
+    level_stats: &mut Arc<Mutex<Vec<LevelInfo>>>,
+    analysis_options: &AnalysisOptions,
+) {
+    debug!("processing song_info={}", level_info_file.display());
+    let mut hasher = Sha1::new();
+    let level_info_src = load_and_hash_json(&mut hasher, &level_info_file);
+    // v4 moved the difficulties, BPM, lights and _customData into other keys and files
+    if let Some(version) = level_info_src["version"].as_str() {
+        warn!(
+            "Skipping song_info={}, its version {} isn't supported",
+            level_info_file.display(),
+            version
+        );
+        return;
+    }
+    let mut level_info = LevelInfo {
+        song: level_info_src["_songName"].as_str().unwrap().to_owned(),
+        artist: level_info_src["_songAuthorName"]
+            .as_str()
+            .unwrap()
+            .to_owned(),
+        mapper: level_info_src["_levelAuthorName"]
+            .as_str()
+            .unwrap()
+            .to_owned(),
+        bpm: level_info_src["_beatsPerMinute"].as_f64().unwrap(),
+        environment: level_info_src["_environmentName"]
+            .as_str()
+            .unwrap()
+            .to_owned(),
+        duration: 0.0,
+        characteristics: HashMap::new(),
+        contributors: custom_data::contributors(&level_info_src),
+        id: "".to_owned(),
+        folder: level_info_file
+            .parent()
+            .and_then(|p| p.file_name())
+            .map(|f| f.to_string_lossy().into_owned()),
+        cover_image: level_info_src["_coverImageFilename"]
+            .as_str()
+            .map(|f| f.to_owned()),
+        missing_locally: false,
+        player: "".to_owned(),
+        player_index: 0,
+        favorite: false,
+    };
+    // some maps list mods for the whole level instead of per difficulty
+    let level_requirements = custom_data::string_list(&level_info_src, "requirements");
+    let level_suggestions = custom_data::string_list(&level_info_src, "suggestions");
+    let characteristics = level_info_src["_difficultyBeatmapSets"].as_array().unwrap();
+    // for each characteristic (e.g. standard, one-hand, 90deg, lawless, etc.)
+    for characteristic_beatmap_set in characteristics {
+        let difficulties = characteristic_beatmap_set["_difficultyBeatmaps"]
+            .as_array()
+            .unwrap();
+        // for each difficulty level on the characteristic
+        for difficulty_info in difficulties {
+            debug!(
+                "Processing song_info={} char={} rank={}",
+                level_info_file.display(),
+                characteristic_beatmap_set["_beatmapCharacteristicName"]
+                    .as_str()
+                    .unwrap(),
+                difficulty_info["_difficultyRank"]
+            );
+            let difficulty_file = load_and_hash_json(
+                &mut hasher,
+                &level_info_file
+                    .parent()
+                    .unwrap()
+                    .join(difficulty_info["_beatmapFilename"].as_str().unwrap()),
+            );
+            // the file still has to be hashed for the level ID, but there's nowhere to put its stats
+            let difficulty_name = match difficulty_info["_difficultyRank"]
+                .as_u64()
+                .and_then(|rank| DIFFICULTY_NAME_MAP.get(rank as usize / 2))
+            {
+                Some(name) => *name,
+                None => {
+                    warn!(
+                        "Skipping difficulty with unknown _difficultyRank={} in song_info={}",
+                        difficulty_info["_difficultyRank"],
+                        level_info_file.display()
+                    );
+                    continue;
+                }
+            };
+            let beatmap = match Beatmap::parse(&difficulty_file, level_info.bpm) {
+                Ok(beatmap) => beatmap,
+                Err(e) => {
+                    warn!(
+                        "Skipping invalid beatmap {} in song_info={}: {}",
+                        difficulty_info["_beatmapFilename"],
+                        level_info_file.display(),
+                        e
+                    );
+                    continue;
+                }
+            };
+            let mut ld = LevelDifficulty {
+                valid: false,
+                plays: 0,
+                rank: "".to_owned(),
+                combo: "".to_owned(),
+                score: 0,
+                peak_nps: Vec::new(),
+                timeline: Option::None,
+                patterns: Option::None,
+                rating: Option::None,
+                max_score: Option::None,
+                requirements: level_requirements.clone(),
+                suggestions: level_suggestions.clone(),
+                label: custom_data::get(difficulty_info, "difficultyLabel")
+                    .as_str()
+                    .map(|l| l.to_owned()),
+                color_left: custom_data::color(difficulty_info, "colorLeft"),
+                color_right: custom_data::color(difficulty_info, "colorRight"),
+                warnings: custom_data::string_list(difficulty_info, "warnings"),
+                information: custom_data::string_list(difficulty_info, "information"),
+                lighting: Option::None,
+                rotation: Option::None,
+                nps: Option::None,
+                notes: Option::None,
+            };
+            for requirement in custom_data::string_list(difficulty_info, "requirements") {
+                if !ld.requirements.contains(&requirement) {
+                    ld.requirements.push(requirement);
+                }
+            }
+            for suggestion in custom_data::string_list(difficulty_info, "suggestions") {
+                if !ld.suggestions.contains(&suggestion) {
+                    ld.suggestions.push(suggestion);
+                }
+            }
+            if !beatmap.notes.is_empty() {
+                let note_times = beatmap
+                    .notes
+                    .iter()
+                    .map(|n| beatmap.tempo_map.seconds(n.beat))
+                    .collect::<Vec<_>>();
+                let notes_duration_seconds = note_times.last().unwrap() - note_times[0];
+                level_info.duration = level_info.duration.max(notes_duration_seconds);
+                ld.nps = Some(beatmap.notes.len() as f64 / notes_duration_seconds);
+                ld.notes = Some(beatmap.notes.len() as u32);
+                ld.patterns = Some(PatternStats::new(&beatmap));
+                ld.rating = Some(Rating::new(&beatmap));
+                ld.max_score = Some(score::max_score(&beatmap));
+
+                // highest NPS in each window
+                ld.peak_nps = analysis_options
+                    .windows
+                    .iter()
+                    .map(|w| PeakDensity {
+                        window: *w,
+                        nps: density::peak_density(&note_times, *w),
+                    })
+                    .collect();
+            }
+            ld.lighting = Some(LightingStats::new(&difficulty_file, &beatmap.tempo_map));
+            let characteristic_name = characteristic_beatmap_set["_beatmapCharacteristicName"]
+                .as_str()
+                .unwrap();
+            if rotation::is_rotation_characteristic(characteristic_name) {
+                ld.rotation = Some(RotationStats::new(&difficulty_file, &beatmap));
+            }
+            ld.timeline = analysis_options
+                .timeline_bucket
+                .map(|bucket| Timeline::new(&beatmap, bucket));
+            if !level_info.characteristics.contains_key(characteristic_name) {
+                // TODO change to or_insert_with
+                level_info.characteristics.insert(
+                    characteristic_name.to_owned(),
+                    LevelCharacteristic {
+                        difficulties: HashMap::new(),
+                    },
+                );
+            }
+            let old_diff = level_info
+                .characteristics
+                .get_mut(characteristic_name)
+                .unwrap()
+                .difficulties
+                .insert(difficulty_name.to_owned(), ld);
+            assert!(old_diff.is_none());
+        }
+    }
+    level_info.id = "custom_level_".to_owned() + &(hasher.finalize().encode_hex_upper::<String>());
+    level_stats.lock().unwrap().push(level_info);
+}
+
+/// Adds OST levels from `ost.csv` and levels that only have scores (DLC or deleted custom levels).
+fn add_unscanned_levels(
+    level_stats: &mut Vec<LevelInfo>,
+    scores: &[Value],
+    beatsaver_dump: Option<&HashMap<String, beatsaver::DumpLevel>>,
+) {
     // OST
-    let mut level_stats = level_stats_arc.lock().unwrap();
     let processed_level_ids = level_stats
         .iter()
         .map(|li| &li.id)
         .collect::<HashSet<&String>>();
-    let unprocessed_scores_by_level_it = player_data["levelsStatsData"]
-        .as_array()
-        .unwrap()
-        .iter()
-        .filter(|score| {
-            !processed_level_ids.contains(&score["levelId"].as_str().unwrap().to_string())
-        });
+    let unprocessed_scores_by_level_it = scores.iter().filter(|score| {
+        !processed_level_ids.contains(&score["levelId"].as_str().unwrap().to_string())
+    });
     let mut unprocessed_scores_by_level =
         HashMap::<String, Vec<&Value>>::with_capacity(unprocessed_scores_by_level_it.size_hint().0);
     for score in unprocessed_scores_by_level_it {
@@ -177,7 +1184,7 @@ fn main() {
             {
                 let diff_idx = score["difficulty"].as_u64().unwrap() as usize;
                 let record_idx_diff_offset = 8 * diff_idx + 5;
-                let ld = LevelDifficulty {
+                let mut ld = LevelDifficulty {
                     valid: score["validScore"].as_bool().unwrap(),
                     plays: score["playCount"].as_u64().unwrap() as u32,
                     rank: SCORE_RANK_MAP[score["maxRank"].as_u64().unwrap() as usize].to_owned(),
@@ -187,10 +1194,24 @@ fn main() {
                         score["maxCombo"].as_u64().unwrap().to_string()
                     },
                     score: score["highScore"].as_u64().unwrap() as u32,
-                    np10s: Option::None,
+                    peak_nps: Vec::new(),
+                    timeline: Option::None,
+                    patterns: Option::None,
+                    rating: Option::None,
+                    max_score: Option::None,
+                    requirements: Vec::new(),
+                    suggestions: Vec::new(),
+                    label: Option::None,
+                    color_left: Option::None,
+                    color_right: Option::None,
+                    warnings: Vec::new(),
+                    information: Vec::new(),
+                    lighting: Option::None,
+                    rotation: Option::None,
                     nps: Some(record[record_idx_diff_offset + 7].parse().unwrap()),
                     notes: Some(record[record_idx_diff_offset + 8].parse().unwrap()),
                 };
+                ld.max_score = ld.notes.map(score::max_score_for_notes);
                 debug!(
                     "got OST score for difficulty={}",
                     DIFFICULTY_NAME_MAP[diff_idx]
@@ -215,7 +1236,14 @@ fn main() {
                         + min_sec_it.next().unwrap().parse::<f64>().unwrap()
                 },
                 characteristics: chars,
+                contributors: Vec::new(),
                 id: level_id.clone(),
+                folder: Option::None,
+                cover_image: Option::None,
+                missing_locally: false,
+                player: "".to_owned(),
+                player_index: 0,
+                favorite: false,
             };
             scores.retain(|s| s["beatmapCharacteristicName"].as_str().unwrap() != "Standard");
             if !scores.is_empty() {
@@ -246,7 +1274,20 @@ fn main() {
                     score["maxCombo"].as_u64().unwrap().to_string()
                 },
                 score: score["highScore"].as_u64().unwrap() as u32,
-                np10s: Option::None,
+                peak_nps: Vec::new(),
+                timeline: Option::None,
+                patterns: Option::None,
+                rating: Option::None,
+                max_score: Option::None,
+                requirements: Vec::new(),
+                suggestions: Vec::new(),
+                label: Option::None,
+                color_left: Option::None,
+                color_right: Option::None,
+                warnings: Vec::new(),
+                information: Vec::new(),
+                lighting: Option::None,
+                rotation: Option::None,
                 nps: Option::None,
                 notes: Option::None,
             };
@@ -258,7 +1299,7 @@ fn main() {
                 .difficulties
                 .insert(diff_name, ld);
         }
-        let li = LevelInfo {
+        let mut li = LevelInfo {
             song: "".to_owned(),
             artist: "".to_owned(),
             mapper: "".to_owned(),
@@ -266,338 +1307,83 @@ fn main() {
             environment: "".to_owned(),
             duration: 0.0,
             characteristics: chars,
+            contributors: Vec::new(),
             id: level_id.clone(),
+            folder: Option::None,
+            cover_image: Option::None,
+            missing_locally: level_id.starts_with("custom_level_"),
+            player: "".to_owned(),
+            player_index: 0,
+            favorite: false,
         };
-        level_stats.push(li);
-    }
-
-    // TODO output enhancements
-    //region output
-    debug!("Progress: finished orphans, starting output");
-    let out_file = Path::new("stats.csv");
-    if out_file.exists() {
-        fs::remove_file(out_file).unwrap();
-    }
-    let mut wtr = csv::Writer::from_path(out_file).unwrap();
-    wtr.write_record(&[
-        "Song",
-        "Artist",
-        "Mapper",
-        "BPM",
-        "Environment",
-        "~Duration",
-        "Characteristic",
-        "Difficulty",
-        "Notes",
-        "~NPS",
-        "NP10S",
-        "Score",
-        "Combo",
-        "Rank",
-        "Plays",
-        "Valid",
-        "ID",
-    ])
-    .unwrap();
-    for l in level_stats.iter() {
-        for c in &l.characteristics {
-            for d in &c.1.difficulties {
-                wtr.write_record([
-                    &l.song,
-                    &l.artist,
-                    &l.mapper,
-                    &l.bpm.to_string(),
-                    &l.environment,
-                    &format!(
-                        "{:02}:{:02}",
-                        (l.duration / 60.0).floor(),
-                        (l.duration % 60.0).floor()
-                    ),
-                    c.0,
-                    d.0,
-                    &{
-                        if let Some(notes) = &d.1.notes {
-                            notes.to_string()
-                        } else {
-                            "".to_owned()
-                        }
-                    },
-                    &{
-                        if let Some(nps) = &d.1.nps {
-                            nps.to_string()
-                        } else {
-                            "".to_owned()
-                        }
-                    },
-                    &{
-                        if let Some(np10s) = &d.1.np10s {
-                            np10s.to_string()
-                        } else {
-                            "".to_owned()
-                        }
-                    },
-                    &d.1.score.to_string(),
-                    &d.1.combo,
-                    &d.1.rank,
-                    &d.1.plays.to_string(),
-                    &d.1.valid.to_string(),
-                    &l.id,
-                ])
-                .unwrap();
+        if let Some(dump_level) =
+            beatsaver::level_hash(&level_id).and_then(|hash| beatsaver_dump?.get(&hash))
+        {
+            debug!("Found orphan id={} in the BeatSaver dump", level_id);
+            li.song = dump_level.song.clone();
+            li.artist = dump_level.artist.clone();
+            li.mapper = dump_level.mapper.clone();
+            li.bpm = dump_level.bpm;
+            li.duration = dump_level.duration;
+            for dump_diff in &dump_level.difficulties {
+                if let Some(ld) = li
+                    .characteristics
+                    .get_mut(&dump_diff.characteristic)
+                    .and_then(|c| c.difficulties.get_mut(&dump_diff.difficulty))
+                {
+                    ld.notes = dump_diff.notes;
+                    ld.nps = dump_diff.nps;
+                    ld.max_score = dump_diff.max_score;
+                }
             }
         }
+        level_stats.push(li);
     }
-    wtr.flush().unwrap();
-    //endregion
 }
 
-const DIFFICULTY_NAME_MAP: [&str; 5] = ["Easy", "Normal", "Hard", "Expert", "Expert+"];
-const SCORE_RANK_MAP: [&str; 8] = ["E", "D", "C", "B", "A", "S", "SS", "SSS"];
-
-fn process_queue(
-    queue: Arc<Mutex<VecDeque<PathBuf>>>,
-    level_stats: &mut Arc<Mutex<Vec<LevelInfo>>>,
-    player_data: Arc<serde_json::Map<String, Value>>,
-) {
-    loop {
-        let current_file;
-        {
-            let mut locked_queue = queue.lock().unwrap();
-            current_file = locked_queue.pop_front();
-        }
-        match current_file {
-            Some(cf) => process_single_song(cf, level_stats, &player_data),
-            None => return,
-        }
+/// Fills in the scores of scanned custom levels.
+fn apply_scores(level_stats: &mut [LevelInfo], scores: &[Value]) {
+    let mut scores_by_level = HashMap::<&str, Vec<&Value>>::new();
+    for score in scores {
+        scores_by_level
+            .entry(score["levelId"].as_str().unwrap())
+            .or_default()
+            .push(score);
     }
-}
-
-fn process_single_song(
-    level_info_file: PathBuf,
-    level_stats: &mut Arc<Mutex<Vec<LevelInfo>>>,
-    player_data: &Arc<serde_json::Map<String, Value>>,
-) {
-    debug!("processing song_info={}", level_info_file.display());
-    let mut hasher = Sha1::new();
-    let level_info_src = load_and_hash_json(&mut hasher, &level_info_file);
-    let mut level_info = LevelInfo {
-        song: level_info_src["_songName"].as_str().unwrap().to_owned(),
-        artist: level_info_src["_songAuthorName"]
-            .as_str()
-            .unwrap()
-            .to_owned(),
-        mapper: level_info_src["_levelAuthorName"]
-            .as_str()
-            .unwrap()
-            .to_owned(),
-        bpm: level_info_src["_beatsPerMinute"].as_f64().unwrap(),
-        environment: level_info_src["_environmentName"]
-            .as_str()
-            .unwrap()
-            .to_owned(),
-        duration: 0.0,
-        characteristics: HashMap::new(),
-        id: "".to_owned(),
-    };
-    let ten_seconds_in_beats = level_info.bpm / 6.0;
-    let characteristics = level_info_src["_difficultyBeatmapSets"].as_array().unwrap();
-    // for each characteristic (e.g. standard, one-hand, 90deg, lawless, etc.)
-    for characteristic_beatmap_set in characteristics {
-        let difficulties = characteristic_beatmap_set["_difficultyBeatmaps"]
-            .as_array()
-            .unwrap();
-        // for each difficulty level on the characteristic
-        for difficulty_info in difficulties {
-            debug!(
-                "Processing song_info={} char={} diff={}",
-                level_info_file.display(),
-                characteristic_beatmap_set["_beatmapCharacteristicName"]
-                    .as_str()
-                    .unwrap(),
-                DIFFICULTY_NAME_MAP
-                    [(difficulty_info["_difficultyRank"].as_u64().unwrap() / 2) as usize]
-            );
-            let difficulty_file = load_and_hash_json(
-                &mut hasher,
-                &level_info_file
-                    .parent()
-                    .unwrap()
-                    .join(difficulty_info["_beatmapFilename"].as_str().unwrap()),
-            );
-            let mut difficulty_ver_str = difficulty_file["version"]
-                .as_str()
-                .unwrap_or("2.0.0")
-                .chars();
-            let beatmap_notes: Vec<Note>;
-            match difficulty_ver_str.next().unwrap() {
-                '3' => {
-                    // TODO safety if these properties don't exist, this could almost certainly be rewritten better
-                    let color_notes = difficulty_file["colorNotes"].as_array().unwrap();
-                    let bomb_notes = difficulty_file["bombNotes"].as_array().unwrap();
-                    let burst_notes = difficulty_file["burstSliders"].as_array().unwrap();
-                    let slider_notes = difficulty_file["sliders"].as_array().unwrap();
-                    let mut all_notes: Vec<Note> = Vec::with_capacity(
-                        color_notes.len()
-                            + bomb_notes.len()
-                            + burst_notes.len()
-                            + 2 * slider_notes.len(),
-                    );
-
-                    all_notes.extend(color_notes.iter().map(|n| Note {
-                        beat: n["b"].as_f64().unwrap(),
-                    }));
-                    all_notes.extend(bomb_notes.iter().map(|n| Note {
-                        beat: n["b"].as_f64().unwrap(),
-                    }));
-                    all_notes.extend(burst_notes.iter().map(|n| Note {
-                        beat: n["b"].as_f64().unwrap(),
-                    }));
-                    all_notes.extend(slider_notes.iter().flat_map(|n| {
-                        vec![
-                            Note {
-                                beat: n["b"].as_f64().unwrap(),
-                            },
-                            Note {
-                                beat: n["tb"].as_f64().unwrap(),
-                            },
-                        ]
-                    }));
-                    all_notes.sort_by(|a, b| a.beat.partial_cmp(&b.beat).unwrap());
-                    beatmap_notes = all_notes;
-                }
-                '2' => match difficulty_ver_str.nth(1).unwrap() {
-                    '6' => {
-                        let color_notes = difficulty_file["_notes"].as_array().unwrap();
-                        let slider_notes = difficulty_file["_sliders"].as_array().unwrap();
-                        let mut all_notes =
-                            Vec::with_capacity(color_notes.len() + 2 * slider_notes.len());
-                        all_notes.extend(color_notes.iter().map(|n| Note {
-                            beat: n["b"].as_f64().unwrap(),
-                        }));
-                        all_notes.extend(slider_notes.iter().flat_map(|n| {
-                            vec![
-                                Note {
-                                    beat: n["b"].as_f64().unwrap(),
-                                },
-                                Note {
-                                    beat: n["tb"].as_f64().unwrap(),
-                                },
-                            ]
-                        }));
-                        all_notes.sort_by(|a, b| a.beat.partial_cmp(&b.beat).unwrap());
-                        beatmap_notes = all_notes;
-                    }
-                    _ => {
-                        beatmap_notes = difficulty_file["_notes"]
-                            .as_array()
-                            .unwrap()
-                            .iter()
-                            .map(|n| Note {
-                                beat: n["_time"].as_f64().unwrap(),
-                            })
-                            .collect();
-                    }
-                },
-                _ => panic!("Unrecognized difficulty file schema version"),
-            }
-            let mut ld = LevelDifficulty {
-                valid: false,
-                plays: 0,
-                rank: "".to_owned(),
-                combo: "".to_owned(),
-                score: 0,
-                np10s: Option::None,
-                nps: Option::None,
-                notes: Option::None,
-            };
-            if !beatmap_notes.is_empty() {
-                let first_note_time = beatmap_notes[0].beat;
-                let last_note_time = beatmap_notes.last().unwrap().beat;
-                let notes_duration_seconds =
-                    (last_note_time - first_note_time) / level_info.bpm * 60.0;
-                level_info.duration = level_info.duration.max(notes_duration_seconds);
-                ld.nps = Some(beatmap_notes.len() as f64 / notes_duration_seconds);
-                ld.notes = Some(beatmap_notes.len() as u32);
-
-                // highest 10-second NPS
-                {
-                    let mut highest_so_far = 0.0;
-                    let mut start_idx = 0;
-                    let mut end_idx = 0;
-                    while end_idx < beatmap_notes.len() - 1 {
-                        let limit = beatmap_notes[start_idx].beat + ten_seconds_in_beats;
-                        while end_idx <= beatmap_notes.len() - 2
-                            && beatmap_notes[end_idx + 1].beat <= limit
-                        {
-                            end_idx += 1;
-                        }
-                        let notes_nps = (end_idx - start_idx + 1) as f64;
-                        if notes_nps > highest_so_far {
-                            highest_so_far = notes_nps;
-                        }
-                        start_idx += 1;
-                    }
-                    ld.np10s = Some((highest_so_far * 10.0).round() / 100.0);
-                }
-            }
-            let characteristic_name = characteristic_beatmap_set["_beatmapCharacteristicName"]
-                .as_str()
-                .unwrap();
+    for level_info in level_stats.iter_mut() {
+        for score in scores_by_level
+            .get(level_info.id.as_str())
+            .into_iter()
+            .flatten()
+        {
+            let characteristic_name = score["beatmapCharacteristicName"].as_str().unwrap();
+            let difficulty_name =
+                DIFFICULTY_NAME_MAP[score["difficulty"].as_u64().unwrap() as usize];
             if !level_info.characteristics.contains_key(characteristic_name) {
-                // TODO change to or_insert_with
-                level_info.characteristics.insert(
-                    characteristic_name.to_owned(),
-                    LevelCharacteristic {
-                        difficulties: HashMap::new(),
-                    },
+                debug!(
+                    "Skipping score processing for id={} char={} diff={}",
+                    level_info.id, characteristic_name, difficulty_name
                 );
+                continue;
             }
-            let difficulty_name = DIFFICULTY_NAME_MAP
-                [(difficulty_info["_difficultyRank"].as_u64().unwrap() / 2) as usize];
-            let old_diff = level_info
+            let ld = level_info
                 .characteristics
                 .get_mut(characteristic_name)
                 .unwrap()
                 .difficulties
-                .insert(difficulty_name.to_owned(), ld);
-            assert!(old_diff.is_none());
-        }
-    }
-    level_info.id = "custom_level_".to_owned() + &(hasher.finalize().encode_hex_upper::<String>());
-    let all_level_scores = player_data["levelsStatsData"]
-        .as_array()
-        .unwrap()
-        .iter()
-        .filter(|l| l["levelId"].as_str().unwrap() == level_info.id);
-    for score in all_level_scores {
-        let characteristic_name = score["beatmapCharacteristicName"].as_str().unwrap();
-        let difficulty_name = DIFFICULTY_NAME_MAP[score["difficulty"].as_u64().unwrap() as usize];
-        if !level_info.characteristics.contains_key(characteristic_name) {
-            debug!(
-                "Skipping score processing for id={} char={} diff={}",
-                level_info.id, characteristic_name, difficulty_name
-            );
-            continue;
+                .get_mut(difficulty_name);
+            let ld = ld.unwrap();
+            ld.valid = score["validScore"].as_bool().unwrap();
+            ld.plays = score["playCount"].as_u64().unwrap() as u32;
+            ld.rank = SCORE_RANK_MAP[score["maxRank"].as_u64().unwrap() as usize].to_owned();
+            ld.combo = if score["fullCombo"].as_bool().unwrap() {
+                "FC".to_owned()
+            } else {
+                score["maxCombo"].as_u64().unwrap().to_string()
+            };
+            ld.score = score["highScore"].as_u64().unwrap() as u32;
         }
-        let ld = level_info
-            .characteristics
-            .get_mut(characteristic_name)
-            .unwrap()
-            .difficulties
-            .get_mut(difficulty_name);
-        let mut ld = ld.unwrap();
-        ld.valid = score["validScore"].as_bool().unwrap();
-        ld.plays = score["playCount"].as_u64().unwrap() as u32;
-        ld.rank = SCORE_RANK_MAP[score["maxRank"].as_u64().unwrap() as usize].to_owned();
-        ld.combo = if score["fullCombo"].as_bool().unwrap() {
-            "FC".to_owned()
-        } else {
-            score["maxCombo"].as_u64().unwrap().to_string()
-        };
-        ld.score = score["highScore"].as_u64().unwrap() as u32;
     }
-
-    level_stats.lock().unwrap().push(level_info);
 }
 
 fn load_and_hash_json(hasher: &mut Sha1, path: &Path) -> Value {
@@ -608,7 +1394,13 @@ fn load_and_hash_json(hasher: &mut Sha1, path: &Path) -> Value {
     serde_json::from_slice(&bytes[..]).unwrap()
 }
 
-#[derive(Serialize)]
+/// Which per-difficulty metrics to compute, shared by all processing threads.
+struct AnalysisOptions {
+    windows: Vec<f64>,            // seconds
+    timeline_bucket: Option<f64>, // seconds, only computed if set
+}
+
+#[derive(Serialize, Clone)]
 struct LevelInfo {
     song: String,
     artist: String,
@@ -617,26 +1409,63 @@ struct LevelInfo {
     environment: String,
     duration: f64, // seconds
     characteristics: HashMap<String, LevelCharacteristic>,
+    contributors: Vec<Contributor>,
     id: String,
+    folder: Option<String>,      // CustomLevels folder name
+    cover_image: Option<String>, // file name in the folder
+    missing_locally: bool,       // a custom level with scores that isn't in CustomLevels
+    player: String,
+    // position in the selected players, which identifies the player since names can repeat
+    #[serde(skip)]
+    player_index: usize,
+    favorite: bool,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 struct LevelCharacteristic {
     difficulties: HashMap<String, LevelDifficulty>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 struct LevelDifficulty {
     valid: bool,
     plays: u32,
     rank: String,
     combo: String,
     score: u32,
-    np10s: Option<f64>,
+    peak_nps: Vec<PeakDensity>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timeline: Option<Timeline>,
+    patterns: Option<PatternStats>,
+    rating: Option<Rating>,
+    max_score: Option<u32>,     // without modifiers
+    requirements: Vec<String>,  // mods
+    suggestions: Vec<String>,   // mods
+    label: Option<String>,      // the mapper's name for the difficulty
+    color_left: Option<String>, // #RRGGBB
+    color_right: Option<String>,
+    warnings: Vec<String>,
+    information: Vec<String>,
+    lighting: Option<LightingStats>,
+    rotation: Option<RotationStats>, // only for 90 and 360 degree characteristics
     nps: Option<f64>,
     notes: Option<u32>,
 }
 
-struct Note {
-    beat: f64,
+impl LevelDifficulty {
+    /// Whether the difficulty is only lights, either by characteristic or by having no notes.
+    fn is_lightshow(&self, characteristic: &str) -> bool {
+        characteristic == "Lightshow"
+            || (self.notes.is_none() && self.lighting.as_ref().is_some_and(|l| l.events > 0))
+    }
+
+    /// Percentage of the max score, which can be over 100 with positive modifiers.
+    fn accuracy(&self) -> Option<f64> {
+        match self.max_score {
+            Some(max_score) if max_score > 0 && self.plays > 0 => Some(density::round2(
+                self.score as f64 * 100.0 / max_score as f64,
+            )),
+            _ => None,
+        }
+    }
 }
@@ -0,0 +1,14 @@
+/// Parses a comma-separated list of installed mods, e.g. `Chroma,Noodle Extensions`.
+pub fn parse_mods(s: &str) -> Vec<String> {
+    s.split(',')
+        .map(|m| m.trim().to_lowercase())
+        .filter(|m| !m.is_empty())
+        .collect()
+}
+
+/// Whether every required mod is in `installed`, which must be lowercase.
+pub fn is_playable_with(requirements: &[String], installed: &[String]) -> bool {
+    requirements
+        .iter()
+        .all(|r| installed.contains(&r.to_lowercase()))
+}
@@ -0,0 +1,356 @@
+use crate::beatmap::{Beatmap, Note, DIRECTION_ANY};
+use crate::density::round2;
+use serde::Serialize;
+
+// notes closer than this many beats are treated as simultaneous
+const SAME_BEAT: f64 = 0.001;
+// longest gap between swings of a stream, in seconds
+const STREAM_GAP: f64 = 0.2;
+// fewest swings in a row that count as a stream or dot spam
+const MIN_RUN: usize = 4;
+
+/// All notes of one color that are hit with a single swing, e.g. a stack or a lone note.
+pub struct Swing<'a> {
+    pub seconds: f64,
+    pub beat: f64,
+    pub color: u8,
+    /// The cut direction of the first directional note, or any if they are all dots.
+    pub direction: u8,
+    pub notes: Vec<&'a Note>,
+}
+
+impl Swing<'_> {
+    /// Average grid position of the notes in the swing, in grid cells even with Mapping Extensions.
+    pub fn position(&self) -> (f64, f64) {
+        let len = self.notes.len() as f64;
+        (
+            self.notes.iter().map(|n| grid_cell(n.x)).sum::<f64>() / len,
+            self.notes.iter().map(|n| grid_cell(n.y)).sum::<f64>() / len,
+        )
+    }
+}
+
+/// Converts a Mapping Extensions precision coordinate (±1000 and beyond) back to grid cells.
+fn grid_cell(v: i64) -> f64 {
+    if v >= 1000 {
+        v as f64 / 1000.0 - 1.0
+    } else if v <= -1000 {
+        v as f64 / 1000.0 + 1.0
+    } else {
+        v as f64
+    }
+}
+
+/// Groups the swing notes of a beatmap into swings, in time order.
+pub fn swings(beatmap: &Beatmap) -> Vec<Swing<'_>> {
+    let mut swings: Vec<Swing> = Vec::new();
+    // index into swings of the latest swing per color
+    let mut latest: [Option<usize>; 2] = [None, None];
+    for note in beatmap.notes.iter().filter(|n| n.is_swing() && n.color < 2) {
+        let color = note.color as usize;
+        if let Some(idx) = latest[color] {
+            let swing = &mut swings[idx];
+            if note.beat - swing.beat < SAME_BEAT {
+                if swing.direction == DIRECTION_ANY {
+                    swing.direction = note.direction;
+                }
+                swing.notes.push(note);
+                continue;
+            }
+        }
+        latest[color] = Some(swings.len());
+        swings.push(Swing {
+            seconds: beatmap.tempo_map.seconds(note.beat),
+            beat: note.beat,
+            color: note.color,
+            direction: note.direction,
+            notes: vec![note],
+        });
+    }
+    swings
+}
+
+/// Unit vector of a cut direction, `None` for dots.
+pub fn direction_vector(direction: u8) -> Option<(f64, f64)> {
+    let diagonal = std::f64::consts::FRAC_1_SQRT_2;
+    match direction {
+        0 => Some((0.0, 1.0)),
+        1 => Some((0.0, -1.0)),
+        2 => Some((-1.0, 0.0)),
+        3 => Some((1.0, 0.0)),
+        4 => Some((-diagonal, diagonal)),
+        5 => Some((diagonal, diagonal)),
+        6 => Some((-diagonal, -diagonal)),
+        7 => Some((diagonal, -diagonal)),
+        _ => None,
+    }
+}
+
+/// How far in degrees a swing turns away from simply reversing the previous swing of the same hand.
+/// 0 is perfect flow (e.g. down then up), 180 is swinging the same way twice.
+pub fn flow_deviation(previous: u8, current: u8) -> Option<f64> {
+    let (px, py) = direction_vector(previous)?;
+    let (cx, cy) = direction_vector(current)?;
+    // angle between the current direction and the reverse of the previous one
+    let cos = (-px * cx - py * cy).clamp(-1.0, 1.0);
+    Some(cos.acos().to_degrees())
+}
+
+/// Map style profile of a difficulty, as the number of swing notes taking part in each pattern.
+#[derive(Serialize, Default, Clone)]
+pub struct PatternStats {
+    /// Color notes, which the percentages are out of.
+    pub notes: u32,
+    /// Single notes alternating hands quickly, at least `MIN_RUN` swings long.
+    pub streams: u32,
+    /// Both hands swinging at the same time.
+    pub jumps: u32,
+    /// Several adjacent notes of one color in one swing.
+    pub stacks: u32,
+    /// Several notes of one color in one swing with a gap between them.
+    pub windows: u32,
+    /// Three or more notes of one color in one column.
+    pub towers: u32,
+    /// A hand swinging on the far side of the other hand's last position.
+    pub crossovers: u32,
+    /// A hand swinging the same way as its previous swing, breaking flow.
+    pub inverts: u32,
+    /// Dots in a run of at least `MIN_RUN` dot swings by one hand.
+    pub dot_spam: u32,
+    /// A hand turning 90 degrees away from reversing its previous swing.
+    pub wide_angles: u32,
+}
+
+impl PatternStats {
+    pub fn new(beatmap: &Beatmap) -> PatternStats {
+        let swings = swings(beatmap);
+        let mut stats = PatternStats {
+            notes: swings.iter().map(|s| s.notes.len() as u32).sum(),
+            ..Default::default()
+        };
+
+        for swing in &swings {
+            let len = swing.notes.len() as u32;
+            if len < 2 {
+                continue;
+            }
+            let mut cells = swing.notes.iter().map(|n| (n.x, n.y)).collect::<Vec<_>>();
+            cells.sort_unstable();
+            let adjacent = cells
+                .windows(2)
+                .all(|w| (w[0].0 - w[1].0).abs() <= 1 && (w[0].1 - w[1].1).abs() <= 1);
+            if len >= 3 && cells.iter().all(|c| c.0 == cells[0].0) && adjacent {
+                stats.towers += len;
+            } else if adjacent {
+                stats.stacks += len;
+            } else {
+                stats.windows += len;
+            }
+        }
+
+        let mut in_jump = vec![false; swings.len()];
+        for i in 1..swings.len() {
+            if swings[i].color != swings[i - 1].color
+                && swings[i].beat - swings[i - 1].beat < SAME_BEAT
+            {
+                in_jump[i - 1] = true;
+                in_jump[i] = true;
+                stats.jumps += (swings[i].notes.len() + swings[i - 1].notes.len()) as u32;
+            }
+        }
+
+        // per hand, indexed by color; dot runs are (swings, notes)
+        let mut last_position: [Option<f64>; 2] = [None, None];
+        let mut last_direction: [Option<u8>; 2] = [None, None];
+        let mut dot_run: [(usize, u32); 2] = [(0, 0), (0, 0)];
+        for swing in &swings {
+            let hand = swing.color as usize;
+            let other = 1 - hand;
+            let len = swing.notes.len() as u32;
+            let x = swing.position().0;
+            if let Some(other_x) = last_position[other] {
+                // red is the left hand, so it crosses over when it is right of blue
+                if (hand == 0 && x > other_x) || (hand == 1 && x < other_x) {
+                    stats.crossovers += len;
+                }
+            }
+            last_position[hand] = Some(x);
+
+            if let Some(previous) = last_direction[hand] {
+                if let Some(deviation) = flow_deviation(previous, swing.direction) {
+                    if deviation > 112.5 {
+                        stats.inverts += len;
+                    } else if deviation > 67.5 {
+                        stats.wide_angles += len;
+                    }
+                }
+            }
+
+            if swing.direction == DIRECTION_ANY {
+                dot_run[hand].0 += 1;
+                dot_run[hand].1 += len;
+                if dot_run[hand].0 == MIN_RUN {
+                    stats.dot_spam += dot_run[hand].1;
+                } else if dot_run[hand].0 > MIN_RUN {
+                    stats.dot_spam += len;
+                }
+            } else {
+                dot_run[hand] = (0, 0);
+                last_direction[hand] = Some(swing.direction);
+            }
+        }
+
+        let mut run_swings = 0;
+        let mut run_notes = 0;
+        for (i, swing) in swings.iter().enumerate() {
+            let continues = i > 0
+                && swing.color != swings[i - 1].color
+                && swing.seconds - swings[i - 1].seconds <= STREAM_GAP;
+            let single = swing.notes.len() == 1 && !in_jump[i];
+            if !single || !continues {
+                if run_swings >= MIN_RUN {
+                    stats.streams += run_notes;
+                }
+                run_swings = 0;
+                run_notes = 0;
+            }
+            if single {
+                run_swings += 1;
+                run_notes += 1;
+            }
+        }
+        if run_swings >= MIN_RUN {
+            stats.streams += run_notes;
+        }
+
+        stats
+    }
+
+    /// Percentage of swing notes, rounded to two decimals.
+    pub fn percent(&self, count: u32) -> f64 {
+        if self.notes == 0 {
+            0.0
+        } else {
+            round2(count as f64 * 100.0 / self.notes as f64)
+        }
+    }
+
+    /// Percentages in the same order as `PATTERN_HEADERS`.
+    pub fn percentages(&self) -> [f64; 9] {
+        [
+            self.percent(self.streams),
+            self.percent(self.jumps),
+            self.percent(self.stacks),
+            self.percent(self.windows),
+            self.percent(self.towers),
+            self.percent(self.crossovers),
+            self.percent(self.inverts),
+            self.percent(self.dot_spam),
+            self.percent(self.wide_angles),
+        ]
+    }
+}
+
+pub const PATTERN_HEADERS: [&str; 9] = [
+    "Streams %",
+    "Jumps %",
+    "Stacks %",
+    "Windows %",
+    "Towers %",
+    "Crossovers %",
+    "Inverts %",
+    "Dot Spam %",
+    "Wide Angles %",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    const RED: u8 = 0;
+    const BLUE: u8 = 1;
+    const UP: u8 = 0;
+    const DOWN: u8 = 1;
+
+    fn note(beat: f64, x: i64, y: i64, color: u8, direction: u8) -> Value {
+        json!({"b": beat, "x": x, "y": y, "c": color, "d": direction})
+    }
+
+    fn stats(notes: Vec<Value>) -> PatternStats {
+        let beatmap = Beatmap::parse(&json!({"version": "3.0.0", "colorNotes": notes}), 120.0);
+        PatternStats::new(&beatmap.unwrap())
+    }
+
+    #[test]
+    fn crossovers() {
+        let stats = stats(vec![
+            note(0.0, 1, 0, RED, DOWN),
+            note(1.0, 2, 0, BLUE, DOWN),
+            // red right of blue's last position
+            note(2.0, 3, 0, RED, UP),
+            // blue left of red's last position
+            note(3.0, 0, 0, BLUE, UP),
+        ]);
+        assert_eq!(stats.notes, 4);
+        assert_eq!(stats.crossovers, 2);
+    }
+
+    #[test]
+    fn mapping_extensions_crossovers() {
+        let stats = stats(vec![
+            note(0.0, 2, 0, BLUE, DOWN),
+            // 1500 is half way between the first two columns, left of blue
+            note(1.0, 1500, 1000, RED, DOWN),
+            // 3500 is half way between the last two columns, right of red
+            note(2.0, 3500, 1000, BLUE, UP),
+            // -2000 is a column left of the grid, left of red
+            note(3.0, -2000, 0, BLUE, DOWN),
+        ]);
+        assert_eq!(stats.crossovers, 1);
+    }
+
+    #[test]
+    fn doubles() {
+        let stats = stats(vec![
+            note(0.0, 1, 0, RED, DOWN),
+            note(0.0, 2, 0, BLUE, DOWN),
+            note(1.0, 1, 0, RED, UP),
+            note(2.0, 2, 0, BLUE, UP),
+            note(3.0, 0, 0, RED, DOWN),
+            note(3.0, 3, 0, BLUE, DOWN),
+        ]);
+        assert_eq!(stats.jumps, 4);
+        assert_eq!(stats.crossovers, 0);
+    }
+
+    #[test]
+    fn resets() {
+        let stats = stats(vec![
+            note(0.0, 1, 0, RED, DOWN),
+            note(1.0, 1, 0, RED, DOWN),
+            note(2.0, 1, 0, RED, UP),
+            // dots don't count, and the next swing is compared with the last directional one
+            note(3.0, 1, 0, RED, DIRECTION_ANY),
+            note(4.0, 1, 0, RED, UP),
+        ]);
+        assert_eq!(stats.inverts, 2);
+        assert_eq!(stats.wide_angles, 0);
+    }
+
+    #[test]
+    fn stacks_windows_and_towers() {
+        let stats = stats(vec![
+            note(0.0, 1, 0, RED, DOWN),
+            note(0.0, 1, 1, RED, DOWN),
+            note(1.0, 0, 0, BLUE, DOWN),
+            note(1.0, 3, 0, BLUE, DOWN),
+            note(2.0, 2, 0, RED, UP),
+            note(2.0, 2, 1, RED, UP),
+            note(2.0, 2, 2, RED, UP),
+        ]);
+        assert_eq!(stats.stacks, 2);
+        assert_eq!(stats.windows, 2);
+        assert_eq!(stats.towers, 3);
+    }
+}
@@ -0,0 +1,317 @@
+use clap::ArgMatches;
+use log::warn;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// The parts of `PlayerData.dat` that are read. Anything missing from the save is left at its
+/// default, since older versions of the game don't write every field.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SaveData {
+    pub local_players: Vec<Player>,
+    #[serde(deserialize_with = "lenient")]
+    pub guest_players: Vec<Player>,
+}
+
+/// One entry of `localPlayers` or `guestPlayers`. Only the scores have to be readable, everything
+/// else falls back to its default if it has an unexpected value.
+#[derive(Deserialize, Serialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Player {
+    /// `playerName`, or `playerId` if the name is empty. Filled in by `select`.
+    #[serde(skip_deserializing)]
+    pub name: String,
+    /// Position in the selected players, which identifies the player since names can repeat, e.g.
+    /// for guests. Filled in by `select`.
+    #[serde(skip)]
+    pub index: usize,
+    #[serde(deserialize_with = "lenient")]
+    pub player_id: String,
+    #[serde(deserialize_with = "lenient")]
+    pub player_name: String,
+    /// Scores are still read as JSON, see `apply_scores` in main.
+    #[serde(rename = "levelsStatsData", skip_serializing)]
+    pub scores: Vec<Value>,
+    #[serde(rename = "favoritesLevelIds", deserialize_with = "lenient")]
+    pub favorite_level_ids: HashSet<String>,
+    #[serde(deserialize_with = "lenient")]
+    pub last_selected_beatmap_difficulty: Option<usize>,
+    #[serde(deserialize_with = "lenient")]
+    pub last_selected_beatmap_characteristic_name: Option<String>,
+    #[serde(deserialize_with = "lenient")]
+    pub gameplay_modifiers: GameplayModifiers,
+    #[serde(deserialize_with = "lenient")]
+    pub player_specific_settings: PlayerSpecificSettings,
+    #[serde(deserialize_with = "lenient")]
+    pub missions_stats_data: Vec<MissionStats>,
+    #[serde(deserialize_with = "lenient")]
+    pub showed_mission_help_ids: Vec<String>,
+    #[serde(deserialize_with = "lenient")]
+    pub player_all_overall_stats_data: AllOverallStats,
+    #[serde(deserialize_with = "lenient")]
+    pub practice_settings: PracticeSettings,
+}
+
+/// The modifiers last selected in solo mode.
+#[derive(Deserialize, Serialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct GameplayModifiers {
+    pub energy_type: u8, // 0 bar, 1 battery
+    pub insta_fail: bool,
+    pub fail_on_saber_clash: bool,
+    pub enabled_obstacle_type: u8, // 0 all, 1 full height only, 2 none
+    pub no_fail: bool,
+    pub no_obstacles: bool,
+    pub no_bombs: bool,
+    pub fast_notes: bool,
+    pub strict_angles: bool,
+    pub disappearing_arrows: bool,
+    pub ghost_notes: bool,
+    pub song_speed: u8, // 0 normal, 1 faster, 2 slower, 3 super fast
+    pub no_arrows: bool,
+    pub pro_mode: bool,
+    pub zen_mode: bool,
+    pub small_cubes: bool,
+}
+
+#[derive(Deserialize, Serialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PlayerSpecificSettings {
+    pub left_handed: bool,
+    pub player_height: f64, // meters
+    pub automatic_player_height: bool,
+    pub static_lights: bool,
+    pub reduce_debris: bool,
+    pub no_texts_and_huds: bool,
+    pub advanced_hud: bool,
+    pub sfx_volume: f64,
+    pub saber_trail_intensity: f64,
+    pub note_jump_start_beat_offset: f64,
+}
+
+#[derive(Deserialize, Serialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MissionStats {
+    pub mission_id: String,
+    pub cleared: bool,
+}
+
+/// Lifetime totals per game mode.
+#[derive(Deserialize, Serialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AllOverallStats {
+    #[serde(rename = "campaignOverallStatsData")]
+    pub campaign: OverallStats,
+    #[serde(rename = "soloFreePlayOverallStatsData")]
+    pub solo_free_play: OverallStats,
+    #[serde(rename = "partyFreePlayOverallStatsData")]
+    pub party_free_play: OverallStats,
+    #[serde(rename = "onlineOverallStatsData")]
+    pub online: OverallStats,
+}
+
+#[derive(Deserialize, Serialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OverallStats {
+    pub good_cuts_count: u64,
+    pub bad_cuts_count: u64,
+    pub missed_cuts_count: u64,
+    pub total_score: u64,
+    pub played_levels_count: u64,
+    // the game's spelling
+    #[serde(rename = "cleardLevelsCount")]
+    pub cleared_levels_count: u64,
+    pub failed_levels_count: u64,
+    pub full_combo_count: u64,
+    pub time_played: f64, // seconds
+    pub hand_distance_travelled: f64,
+}
+
+#[derive(Deserialize, Serialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PracticeSettings {
+    pub start_song_time: f64, // seconds
+    pub song_speed_mul: f64,
+}
+
+/// Deserializes a field that isn't needed for the stats, falling back to its default if the save has
+/// a value of another type, e.g. `null` or an unknown enum value, instead of failing the whole run.
+fn lenient<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned + Default,
+{
+    let value = Value::deserialize(deserializer)?;
+    Ok(serde_json::from_value(value).unwrap_or_else(|e| {
+        warn!("Ignoring unexpected value in the save file: {}", e);
+        T::default()
+    }))
+}
+
+impl GameplayModifiers {
+    /// Names of the modifiers that are on.
+    pub fn enabled(&self) -> Vec<&str> {
+        let mut enabled = Vec::new();
+        for (on, name) in [
+            (self.energy_type == 1, "4 lives"),
+            (self.insta_fail, "1 life"),
+            (self.no_fail, "no fail"),
+            (
+                self.no_obstacles || self.enabled_obstacle_type == 2,
+                "no walls",
+            ),
+            (self.no_bombs, "no bombs"),
+            (self.no_arrows, "no arrows"),
+            (self.ghost_notes, "ghost notes"),
+            (self.disappearing_arrows, "disappearing arrows"),
+            (self.small_cubes, "small notes"),
+            (self.pro_mode, "pro mode"),
+            (self.strict_angles, "strict angles"),
+            (self.zen_mode, "zen mode"),
+            (self.fast_notes, "fast notes"),
+            (self.fail_on_saber_clash, "fail on saber clash"),
+            (self.song_speed == 1, "faster song"),
+            (self.song_speed == 2, "slower song"),
+            (self.song_speed == 3, "super fast song"),
+        ] {
+            if on {
+                enabled.push(name);
+            }
+        }
+        enabled
+    }
+}
+
+/// Picks the players to output from the save file: every local and guest player if `all`,
+/// otherwise the local player at `player_number`.
+pub fn select(save: SaveData, all: bool, player_number: usize) -> Vec<Player> {
+    let local_players_len = save.local_players.len();
+    let mut players = if all {
+        let players = save
+            .local_players
+            .into_iter()
+            .chain(save.guest_players)
+            .collect::<Vec<_>>();
+        if players.is_empty() {
+            panic!("The save file has no local or guest players");
+        }
+        players
+    } else {
+        match save.local_players.into_iter().nth(player_number) {
+            Some(player) => vec![player],
+            None if local_players_len == 0 => panic!("The save file has no local players"),
+            None => panic!(
+                "Player number {} not found, the save file has {} local players (numbered from 0)",
+                player_number, local_players_len
+            ),
+        }
+    };
+    for (index, player) in players.iter_mut().enumerate() {
+        player.index = index;
+        player.name = if player.player_name.is_empty() {
+            player.player_id.clone()
+        } else {
+            player.player_name.clone()
+        };
+    }
+    players
+}
+
+/// Prints each player's campaign progress, favorites, modifiers, settings and overall stats.
+pub fn run(players: &[Player], args: &ArgMatches) {
+    if args.is_present("json") {
+        println!("{}", serde_json::to_string_pretty(players).unwrap());
+        return;
+    }
+    for player in players {
+        println!("{} ({})", player.name, player.player_id);
+        let cleared = player
+            .missions_stats_data
+            .iter()
+            .filter(|m| m.cleared)
+            .collect::<Vec<_>>();
+        println!(
+            "  Campaign: {} of {} attempted missions cleared",
+            cleared.len(),
+            player.missions_stats_data.len()
+        );
+        if !cleared.is_empty() {
+            println!(
+                "    Cleared: {}",
+                cleared
+                    .iter()
+                    .map(|m| m.mission_id.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        println!("  Favorites: {} levels", player.favorite_level_ids.len());
+        if let (Some(characteristic), Some(difficulty)) = (
+            &player.last_selected_beatmap_characteristic_name,
+            player.last_selected_beatmap_difficulty,
+        ) {
+            println!(
+                "  Last selected: {} {}",
+                characteristic,
+                crate::DIFFICULTY_NAME_MAP
+                    .get(difficulty)
+                    .unwrap_or(&"Unknown")
+            );
+        }
+        let modifiers = player.gameplay_modifiers.enabled();
+        println!(
+            "  Modifiers: {}",
+            if modifiers.is_empty() {
+                "none".to_owned()
+            } else {
+                modifiers.join(", ")
+            }
+        );
+        let settings = &player.player_specific_settings;
+        println!(
+            "  Settings: player height {} m{}, note jump offset {}{}{}",
+            settings.player_height,
+            if settings.automatic_player_height {
+                " (automatic)"
+            } else {
+                ""
+            },
+            settings.note_jump_start_beat_offset,
+            if settings.left_handed {
+                ", left handed"
+            } else {
+                ""
+            },
+            if settings.static_lights {
+                ", static lights"
+            } else {
+                ""
+            },
+        );
+        let overall = &player.player_all_overall_stats_data;
+        for (mode, stats) in [
+            ("Campaign", &overall.campaign),
+            ("Solo", &overall.solo_free_play),
+            ("Party", &overall.party_free_play),
+            ("Online", &overall.online),
+        ] {
+            if stats.played_levels_count == 0 {
+                continue;
+            }
+            println!(
+                "  {}: {} played, {} cleared, {} failed, {} full combos, {} good cuts, {:02}:{:02}:{:02} played",
+                mode,
+                stats.played_levels_count,
+                stats.cleared_levels_count,
+                stats.failed_levels_count,
+                stats.full_combo_count,
+                stats.good_cuts_count,
+                (stats.time_played / 3600.0).floor(),
+                (stats.time_played % 3600.0 / 60.0).floor(),
+                (stats.time_played % 60.0).floor()
+            );
+        }
+    }
+}
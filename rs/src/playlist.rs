@@ -0,0 +1,345 @@
+use crate::density::round2;
+use crate::filter::Row;
+use crate::players::Player;
+use crate::{LevelInfo, DIFFICULTY_NAME_MAP};
+use clap::ArgMatches;
+use log::warn;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// A playlist in the `.bplist` format read by PlaylistManager.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Playlist {
+    pub playlist_title: String,
+    pub playlist_author: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>, // base64
+    pub songs: Vec<PlaylistSong>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistSong {
+    pub song_name: String,
+    pub level_author_name: String,
+    /// Only for custom levels.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    #[serde(rename = "levelid")]
+    pub level_id: String,
+    pub difficulties: Vec<PlaylistDifficulty>,
+}
+
+#[derive(Serialize, PartialEq)]
+pub struct PlaylistDifficulty {
+    pub characteristic: String,
+    /// e.g. `ExpertPlus`
+    pub name: String,
+}
+
+impl Playlist {
+    /// One song per level in the order the rows first mention it, with every row's difficulty.
+    pub fn new(rows: &[Row], title: &str, author: &str) -> Playlist {
+        let mut songs = Vec::<PlaylistSong>::new();
+        // index into songs by level ID
+        let mut song_index = HashMap::<&str, usize>::new();
+        for row in rows {
+            let difficulty = PlaylistDifficulty {
+                characteristic: row.characteristic.to_owned(),
+                name: row.difficulty.replace('+', "Plus"),
+            };
+            let song = match song_index.get(row.level.id.as_str()) {
+                Some(&i) => &mut songs[i],
+                None => {
+                    song_index.insert(&row.level.id, songs.len());
+                    songs.push(PlaylistSong {
+                        song_name: row.level.song.clone(),
+                        level_author_name: row.level.mapper.clone(),
+                        hash: row
+                            .level
+                            .id
+                            .strip_prefix("custom_level_")
+                            .map(|h| h.to_owned()),
+                        level_id: row.level.id.clone(),
+                        difficulties: Vec::new(),
+                    });
+                    songs.last_mut().unwrap()
+                }
+            };
+            // with --all-players, every player has a row for the same difficulty
+            if !song.difficulties.contains(&difficulty) {
+                song.difficulties.push(difficulty);
+            }
+        }
+        Playlist {
+            playlist_title: title.to_owned(),
+            playlist_author: author.to_owned(),
+            image: None,
+            songs,
+        }
+    }
+
+    pub fn write(&self, path: &Path) {
+        serde_json::to_writer_pretty(fs::File::create(path).unwrap(), self).unwrap();
+    }
+}
+
+/// Base64 of the cover image file, as PlaylistManager expects in `image`.
+pub fn encode_image(path: &Path) -> String {
+    let bytes = fs::read(path)
+        .unwrap_or_else(|e| panic!("Can't read playlist cover {}: {}", path.display(), e));
+    base64(&bytes)
+}
+
+pub fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// How far a player is through one playlist from the game's `Playlists` folder.
+#[derive(Serialize)]
+pub struct PlaylistProgress {
+    pub file: String,
+    pub title: String,
+    pub player: String,
+    pub songs: u32,
+    pub songs_played: u32,
+    /// Difficulties highlighted in the playlist, or every difficulty of songs without any.
+    pub difficulties: u32,
+    pub difficulties_played: u32,
+    pub full_combos: u32,
+    pub average_accuracy: Option<f64>,
+    /// Custom levels in the playlist that aren't in CustomLevels.
+    pub missing: Vec<String>,
+}
+
+/// Reads every `.bplist` in `playlists_path`, sorted by file name.
+pub fn read_playlists(playlists_path: &Path) -> Vec<(String, Value)> {
+    if !playlists_path.is_dir() {
+        warn!("No playlists found at {}", playlists_path.display());
+        return Vec::new();
+    }
+    let mut paths = playlists_path
+        .read_dir()
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|p| {
+            p.extension()
+                .is_some_and(|e| e.eq_ignore_ascii_case("bplist"))
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths
+        .iter()
+        .filter_map(|p| {
+            let file = p.file_name().unwrap().to_string_lossy().into_owned();
+            match serde_json::from_slice::<Value>(&fs::read(p).unwrap()) {
+                Ok(playlist) => Some((file, playlist)),
+                Err(e) => {
+                    warn!("Skipping playlist {} that isn't JSON: {}", p.display(), e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+impl PlaylistProgress {
+    /// Resolves each song of the playlist against one player's levels.
+    pub fn new(file: &str, playlist: &Value, player: &str, level_stats: &[&LevelInfo]) -> Self {
+        let mut progress = PlaylistProgress {
+            file: file.to_owned(),
+            title: playlist["playlistTitle"]
+                .as_str()
+                .unwrap_or(file)
+                .to_owned(),
+            player: player.to_owned(),
+            songs: 0,
+            songs_played: 0,
+            difficulties: 0,
+            difficulties_played: 0,
+            full_combos: 0,
+            average_accuracy: None,
+            missing: Vec::new(),
+        };
+        let mut accuracies = Vec::new();
+        for song in playlist["songs"].as_array().into_iter().flatten() {
+            progress.songs += 1;
+            let level_id = match (song["hash"].as_str(), song["levelid"].as_str()) {
+                (Some(hash), _) => format!("custom_level_{}", hash.to_ascii_uppercase()),
+                (None, Some(level_id)) => level_id.to_owned(),
+                (None, None) => continue,
+            };
+            let level = level_stats.iter().find(|l| l.id == level_id);
+            if level.is_none_or(|l| l.missing_locally) && level_id.starts_with("custom_level_") {
+                progress.missing.push(match song["songName"].as_str() {
+                    Some(name) => format!("{} ({})", name, level_id),
+                    None => level_id.clone(),
+                });
+            }
+            let level = match level {
+                Some(l) => l,
+                None => continue,
+            };
+            let highlighted = song["difficulties"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|d| {
+                    Some((
+                        d["characteristic"].as_str()?.to_owned(),
+                        difficulty_name(d["name"].as_str()?)?,
+                    ))
+                })
+                .collect::<Vec<_>>();
+            let mut song_played = false;
+            for c in &level.characteristics {
+                for d in &c.1.difficulties {
+                    if !highlighted.is_empty()
+                        && !highlighted
+                            .iter()
+                            .any(|h| h.0.eq_ignore_ascii_case(c.0) && h.1 == *d.0)
+                    {
+                        continue;
+                    }
+                    progress.difficulties += 1;
+                    if d.1.plays > 0 {
+                        song_played = true;
+                        progress.difficulties_played += 1;
+                        progress.full_combos += (d.1.combo == "FC") as u32;
+                        accuracies.extend(d.1.accuracy());
+                    }
+                }
+            }
+            progress.songs_played += song_played as u32;
+        }
+        if !accuracies.is_empty() {
+            progress.average_accuracy = Some(round2(
+                accuracies.iter().sum::<f64>() / accuracies.len() as f64,
+            ));
+        }
+        progress
+    }
+}
+
+/// A playlist difficulty name like `ExpertPlus` (any case) as used in the output, e.g. `Expert+`.
+fn difficulty_name(name: &str) -> Option<&'static str> {
+    let name = name.to_ascii_lowercase().replace("plus", "+");
+    DIFFICULTY_NAME_MAP
+        .iter()
+        .find(|n| n.to_ascii_lowercase() == name)
+        .copied()
+}
+
+/// Prints each player's progress through each playlist.
+pub fn run(
+    playlists_path: &Path,
+    level_stats: &[LevelInfo],
+    players: &[Player],
+    args: &ArgMatches,
+) {
+    let playlists = read_playlists(playlists_path);
+    let mut progress = Vec::new();
+    for player in players {
+        let levels = level_stats
+            .iter()
+            .filter(|l| l.player_index == player.index)
+            .collect::<Vec<_>>();
+        for (file, playlist) in &playlists {
+            progress.push(PlaylistProgress::new(file, playlist, &player.name, &levels));
+        }
+    }
+    if args.is_present("json") {
+        println!("{}", serde_json::to_string_pretty(&progress).unwrap());
+        return;
+    }
+    for p in &progress {
+        if players.len() > 1 {
+            println!("{} ({}) - {}", p.title, p.file, p.player);
+        } else {
+            println!("{} ({})", p.title, p.file);
+        }
+        println!("  Songs played: {} of {}", p.songs_played, p.songs);
+        println!(
+            "  Difficulties played: {} of {}, {} full combos",
+            p.difficulties_played, p.difficulties, p.full_combos
+        );
+        if let Some(accuracy) = p.average_accuracy {
+            println!("  Average accuracy: {}%", accuracy);
+        }
+        if !p.missing.is_empty() {
+            println!("  Missing: {}", p.missing.join(", "));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{add_difficulty, difficulty, level};
+    use crate::sort;
+
+    #[test]
+    fn base64_rfc_4648_vectors() {
+        for (input, output) in [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(base64(input.as_bytes()), output);
+        }
+        assert_eq!(base64(&[0xfb, 0xff, 0xbf]), "+/+/");
+    }
+
+    #[test]
+    fn one_song_per_level() {
+        let mut level_stats = Vec::new();
+        for player in ["Alice", "Bob"] {
+            for id in ["custom_level_AAAA", "1", "custom_level_BBBB"] {
+                let mut l = level(id, player);
+                add_difficulty(&mut l, "Expert", difficulty(8.0, ""));
+                add_difficulty(&mut l, "Expert+", difficulty(9.0, ""));
+                level_stats.push(l);
+            }
+        }
+        let playlist = Playlist::new(&sort::rows(&level_stats), "Title", "Author");
+        let ids = playlist
+            .songs
+            .iter()
+            .map(|s| s.level_id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["custom_level_AAAA", "1", "custom_level_BBBB"]);
+        assert_eq!(playlist.songs[0].hash.as_deref(), Some("AAAA"));
+        assert_eq!(playlist.songs[1].hash, None);
+        let names = playlist.songs[2]
+            .difficulties
+            .iter()
+            .map(|d| d.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["Expert", "ExpertPlus"]);
+    }
+}
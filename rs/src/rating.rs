@@ -0,0 +1,128 @@
+use crate::beatmap::Beatmap;
+use crate::density::{peak_density, round2};
+use crate::patterns::{flow_deviation, swings};
+use serde::Serialize;
+
+// gaps shorter than this are treated as this, so near-simultaneous swings don't explode the rating
+const MIN_GAP: f64 = 0.05;
+
+/// Reproducible difficulty estimate from swing angles, hand movement and timing.
+///
+/// For every swing after a hand's first, with `rate` = 1 / seconds since that hand's previous swing
+/// and `travel` = grid cells moved since then:
+/// * Speed is the average of the NP10S and the mean of the hardest third of `rate * (1 + travel / 3)`.
+/// * Tech is the mean of `angle / 45 * (1 + travel / 3) * sqrt(rate)`, where `angle` is how far the
+///   swing turns away from reversing the previous one (0 for dots).
+#[derive(Serialize, Clone)]
+pub struct Rating {
+    pub tech: f64,
+    pub speed: f64,
+}
+
+impl Rating {
+    pub fn new(beatmap: &Beatmap) -> Rating {
+        let swings = swings(beatmap);
+        let mut speed_costs = Vec::with_capacity(swings.len());
+        let mut tech_costs = Vec::with_capacity(swings.len());
+        // index of each hand's previous swing
+        let mut previous: [Option<usize>; 2] = [None, None];
+        for (i, swing) in swings.iter().enumerate() {
+            let hand = swing.color as usize;
+            if let Some(p) = previous[hand] {
+                let prev = &swings[p];
+                let rate = 1.0 / (swing.seconds - prev.seconds).max(MIN_GAP);
+                let (px, py) = prev.position();
+                let (x, y) = swing.position();
+                let travel = ((x - px).powi(2) + (y - py).powi(2)).sqrt();
+                let angle = flow_deviation(prev.direction, swing.direction).unwrap_or(0.0);
+                speed_costs.push(rate * (1.0 + travel / 3.0));
+                tech_costs.push(angle / 45.0 * (1.0 + travel / 3.0) * rate.sqrt());
+            }
+            previous[hand] = Some(i);
+        }
+        if speed_costs.is_empty() {
+            return Rating {
+                tech: 0.0,
+                speed: 0.0,
+            };
+        }
+
+        speed_costs.sort_by(|a, b| b.partial_cmp(a).unwrap());
+        let hardest = &speed_costs[..speed_costs.len().div_ceil(3)];
+        let sustained_speed = hardest.iter().sum::<f64>() / hardest.len() as f64;
+        let note_times = beatmap
+            .notes
+            .iter()
+            .map(|n| beatmap.tempo_map.seconds(n.beat))
+            .collect::<Vec<_>>();
+        Rating {
+            tech: round2(tech_costs.iter().sum::<f64>() / tech_costs.len() as f64),
+            speed: round2((peak_density(&note_times, 10.0) + sustained_speed) / 2.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A beatmap of red notes every `spacing` beats at 120 BPM, with the given directions and
+    /// columns repeated.
+    fn rating(count: usize, spacing: f64, directions: &[u8], columns: &[i64]) -> Rating {
+        let notes = (0..count)
+            .map(|i| {
+                json!({
+                    "b": i as f64 * spacing,
+                    "x": columns[i % columns.len()],
+                    "y": 0,
+                    "c": 0,
+                    "d": directions[i % directions.len()],
+                })
+            })
+            .collect::<Vec<_>>();
+        let beatmap = Beatmap::parse(&json!({"version": "3.0.0", "colorNotes": notes}), 120.0);
+        Rating::new(&beatmap.unwrap())
+    }
+
+    #[test]
+    fn down_up_every_half_second() {
+        // 2 swings per second without moving, and 20 notes in the first 10 seconds
+        let rating = rating(20, 1.0, &[1, 0], &[1]);
+        assert_eq!(rating.speed, 2.0);
+        assert_eq!(rating.tech, 0.0);
+    }
+
+    #[test]
+    fn faster_is_more_speed() {
+        let slow = rating(40, 1.0, &[1, 0], &[1]);
+        let fast = rating(40, 0.5, &[1, 0], &[1]);
+        assert!(fast.speed > slow.speed);
+        assert_eq!(fast.tech, 0.0);
+    }
+
+    #[test]
+    fn moving_is_more_speed() {
+        let still = rating(40, 1.0, &[1, 0], &[1]);
+        let moving = rating(40, 1.0, &[1, 0], &[0, 3]);
+        assert!(moving.speed > still.speed);
+    }
+
+    #[test]
+    fn angles_are_more_tech() {
+        let flowing = rating(40, 1.0, &[1, 0], &[1]);
+        // down then right turns 90 degrees away from reversing
+        let angled = rating(40, 1.0, &[1, 3, 0, 2], &[1]);
+        // down then down turns 180 degrees
+        let resets = rating(40, 1.0, &[1], &[1]);
+        assert!(angled.tech > flowing.tech);
+        assert!(resets.tech > angled.tech);
+    }
+
+    #[test]
+    fn one_swing_per_hand() {
+        let rating = rating(1, 1.0, &[1], &[1]);
+        assert_eq!(rating.speed, 0.0);
+        assert_eq!(rating.tech, 0.0);
+    }
+}
@@ -0,0 +1,182 @@
+use crate::density::round2;
+use crate::filter::{Row, Value};
+use crate::players::Player;
+use crate::playlist::Playlist;
+use crate::{sort, LevelInfo};
+use clap::ArgMatches;
+use std::path::Path;
+
+// a score at least this accurate (an S rank) counts as comfortable
+const COMFORTABLE_ACCURACY: f64 = 80.0;
+// how many of the hardest comfortable difficulties the comfortable NP10S is averaged over
+const HARDEST_COMFORTABLE: usize = 5;
+
+/// A difficulty to practice, with why it was picked.
+pub struct Recommendation<'a> {
+    pub row: Row<'a>,
+    pub np10s: f64,
+    pub reason: String,
+}
+
+fn number(row: &Row, field: &str) -> Option<f64> {
+    match row.value(field) {
+        Value::Number(n) => Some(n),
+        _ => None,
+    }
+}
+
+/// The NP10S a player is comfortable at: the average of the hardest difficulties they have an S or
+/// better accuracy on, or the hardest they have played if there are none. `None` without scores.
+pub fn comfortable_np10s(rows: &[Row]) -> Option<f64> {
+    let played = rows
+        .iter()
+        .filter(|r| r.stats.plays > 0)
+        .filter_map(|r| Some((number(r, "np10s")?, number(r, "accuracy"))))
+        .collect::<Vec<_>>();
+    let mut comfortable = played
+        .iter()
+        .filter(|p| p.1.is_some_and(|a| a >= COMFORTABLE_ACCURACY))
+        .map(|p| p.0)
+        .collect::<Vec<_>>();
+    if comfortable.is_empty() {
+        return played.iter().map(|p| p.0).reduce(f64::max);
+    }
+    comfortable.sort_by(|a, b| b.partial_cmp(a).unwrap());
+    comfortable.truncate(HARDEST_COMFORTABLE);
+    Some(round2(
+        comfortable.iter().sum::<f64>() / comfortable.len() as f64,
+    ))
+}
+
+/// Unplayed or weakly scored difficulties with an NP10S above `comfortable` by at most `stretch`
+/// percent, easiest first.
+pub fn recommend<'a>(rows: &[Row<'a>], comfortable: f64, stretch: f64) -> Vec<Recommendation<'a>> {
+    let max_np10s = comfortable * (1.0 + stretch / 100.0);
+    let mut recommendations = rows
+        .iter()
+        .filter_map(|row| {
+            let np10s = number(row, "np10s")?;
+            if np10s <= comfortable || np10s > max_np10s {
+                return None;
+            }
+            let above = format!(
+                "NP10S {} is {}% above your comfortable {}",
+                np10s,
+                round2((np10s / comfortable - 1.0) * 100.0),
+                comfortable
+            );
+            let reason = if row.stats.plays == 0 {
+                format!("{}, not played yet", above)
+            } else {
+                match number(row, "accuracy") {
+                    Some(accuracy) if accuracy < COMFORTABLE_ACCURACY => format!(
+                        "{}, best accuracy {}% ({})",
+                        above, accuracy, row.stats.rank
+                    ),
+                    Some(_) => return None,
+                    None => format!("{}, best score {}", above, row.stats.score),
+                }
+            };
+            Some(Recommendation {
+                row: *row,
+                np10s,
+                reason,
+            })
+        })
+        .collect::<Vec<_>>();
+    recommendations.sort_by(|a, b| a.np10s.partial_cmp(&b.np10s).unwrap());
+    recommendations
+}
+
+/// Prints recommendations for each player, and writes them to `--csv` and `--playlist` if given.
+pub fn run(level_stats: &[LevelInfo], players: &[Player], args: &ArgMatches, author: &str) {
+    let stretch = args.value_of("stretch").unwrap().parse::<f64>().unwrap();
+    let count = args.value_of("count").unwrap().parse::<usize>().unwrap();
+    let rows = sort::rows(level_stats);
+    let mut all_recommendations = Vec::new();
+    for player in players {
+        let player_rows = rows
+            .iter()
+            .filter(|r| r.level.player_index == player.index)
+            .copied()
+            .collect::<Vec<_>>();
+        let comfortable = match comfortable_np10s(&player_rows) {
+            Some(c) => c,
+            None => {
+                println!("{}: no scores with an NP10S to estimate from", player.name);
+                continue;
+            }
+        };
+        let mut recommendations = recommend(&player_rows, comfortable, stretch);
+        recommendations.truncate(count);
+        println!(
+            "{}: comfortable at NP10S {}, {} recommendations",
+            player.name,
+            comfortable,
+            recommendations.len()
+        );
+        for r in &recommendations {
+            println!(
+                "  {} - {} ({}) {} {}: {}",
+                r.row.level.song,
+                r.row.level.artist,
+                r.row.level.mapper,
+                r.row.characteristic,
+                r.row.difficulty,
+                r.reason
+            );
+        }
+        all_recommendations.extend(recommendations);
+    }
+
+    if let Some(csv_path) = args.value_of("csv") {
+        let mut wtr = csv::Writer::from_path(csv_path).unwrap();
+        wtr.write_record([
+            "Player",
+            "Song",
+            "Artist",
+            "Mapper",
+            "Characteristic",
+            "Difficulty",
+            "NP10S",
+            "Accuracy %",
+            "Rank",
+            "Reason",
+            "ID",
+        ])
+        .unwrap();
+        for r in &all_recommendations {
+            wtr.write_record([
+                r.row.level.player.clone(),
+                r.row.level.song.clone(),
+                r.row.level.artist.clone(),
+                r.row.level.mapper.clone(),
+                r.row.characteristic.to_owned(),
+                r.row.difficulty.to_owned(),
+                r.np10s.to_string(),
+                r.row
+                    .stats
+                    .accuracy()
+                    .map(|a| a.to_string())
+                    .unwrap_or_default(),
+                r.row.stats.rank.clone(),
+                r.reason.clone(),
+                r.row.level.id.clone(),
+            ])
+            .unwrap();
+        }
+        wtr.flush().unwrap();
+    }
+    if let Some(playlist_path) = args.value_of("playlist").map(Path::new) {
+        let rows = all_recommendations
+            .iter()
+            .map(|r| r.row)
+            .collect::<Vec<_>>();
+        Playlist::new(
+            &rows,
+            &playlist_path.file_stem().unwrap().to_string_lossy(),
+            author,
+        )
+        .write(playlist_path);
+    }
+}
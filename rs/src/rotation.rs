@@ -0,0 +1,85 @@
+use crate::beatmap::Beatmap;
+use crate::density::round2;
+use serde::Serialize;
+use serde_json::Value;
+
+// v2 rotation event values 0-7 in degrees
+const V2_ROTATIONS: [f64; 8] = [-60.0, -45.0, -30.0, -15.0, 15.0, 30.0, 45.0, 60.0];
+const V2_EARLY_ROTATION: u64 = 14;
+const V2_LATE_ROTATION: u64 = 15;
+
+/// Rotation of the play area in 90 and 360 degree difficulties.
+#[derive(Serialize, Clone)]
+pub struct RotationStats {
+    pub events: u32,
+    /// Sum of every rotation regardless of direction, in degrees.
+    pub total: f64,
+    /// Largest rotation in one direction before turning back, in degrees.
+    pub max_continuous: f64,
+    /// Rotation events per minute between the first and last note.
+    pub per_minute: f64,
+}
+
+/// Whether rotation is part of the characteristic's gameplay.
+pub fn is_rotation_characteristic(characteristic: &str) -> bool {
+    characteristic == "90Degree" || characteristic == "360Degree"
+}
+
+impl RotationStats {
+    pub fn new(difficulty_file: &Value, beatmap: &Beatmap) -> RotationStats {
+        // (beat, degrees)
+        let mut rotations = Vec::<(f64, f64)>::new();
+        if let Some(events) = difficulty_file["_events"].as_array() {
+            rotations.extend(
+                events
+                    .iter()
+                    .filter(|e| {
+                        let event_type = e["_type"].as_u64();
+                        event_type == Some(V2_EARLY_ROTATION)
+                            || event_type == Some(V2_LATE_ROTATION)
+                    })
+                    .filter_map(|e| {
+                        let degrees = e["_customData"]["_rotation"].as_f64().or_else(|| {
+                            V2_ROTATIONS.get(e["_value"].as_u64()? as usize).copied()
+                        })?;
+                        Some((e["_time"].as_f64()?, degrees))
+                    }),
+            );
+        }
+        if let Some(events) = difficulty_file["rotationEvents"].as_array() {
+            rotations.extend(
+                events
+                    .iter()
+                    .filter_map(|e| Some((e["b"].as_f64()?, e["r"].as_f64()?))),
+            );
+        }
+        rotations.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+        let mut max_continuous: f64 = 0.0;
+        let mut current_run: f64 = 0.0;
+        for (_, degrees) in &rotations {
+            if current_run * degrees < 0.0 {
+                current_run = 0.0;
+            }
+            current_run += degrees;
+            max_continuous = max_continuous.max(current_run.abs());
+        }
+        let minutes = match (beatmap.notes.first(), beatmap.notes.last()) {
+            (Some(first), Some(last)) => {
+                (beatmap.tempo_map.seconds(last.beat) - beatmap.tempo_map.seconds(first.beat))
+                    / 60.0
+            }
+            _ => 0.0,
+        };
+        RotationStats {
+            events: rotations.len() as u32,
+            total: rotations.iter().map(|r| r.1.abs()).sum(),
+            max_continuous,
+            per_minute: if minutes > 0.0 {
+                round2(rotations.len() as f64 / minutes)
+            } else {
+                0.0
+            },
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use crate::beatmap::{Beatmap, NoteKind};
+use crate::SCORE_RANK_MAP;
+
+const NOTE_MAX: u32 = 115;
+// a chain head gets no post-swing points
+const CHAIN_HEAD_MAX: u32 = 85;
+const CHAIN_LINK_MAX: u32 = 20;
+
+/// Highest possible score without modifiers, following the game's combo multiplier ramp.
+pub fn max_score(beatmap: &Beatmap) -> u32 {
+    let chain_heads = beatmap
+        .notes
+        .iter()
+        .filter(|n| n.kind == NoteKind::ChainHead)
+        .collect::<Vec<_>>();
+    let mut cuts = beatmap
+        .notes
+        .iter()
+        .filter(|n| n.kind == NoteKind::Color)
+        .map(|n| {
+            // the color note at the start of a chain becomes its head
+            let is_chain_head = chain_heads.iter().any(|h| {
+                (h.beat - n.beat).abs() < 0.001 && h.x == n.x && h.y == n.y && h.color == n.color
+            });
+            (
+                n.beat,
+                if is_chain_head {
+                    CHAIN_HEAD_MAX
+                } else {
+                    NOTE_MAX
+                },
+            )
+        })
+        .chain(beatmap.chain_links.iter().map(|b| (*b, CHAIN_LINK_MAX)))
+        .collect::<Vec<_>>();
+    cuts.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    with_multiplier(cuts.iter().map(|c| c.1))
+}
+
+/// Highest possible score for a map of only normal notes, e.g. from `ost.csv`.
+pub fn max_score_for_notes(notes: u32) -> u32 {
+    with_multiplier((0..notes).map(|_| NOTE_MAX))
+}
+
+fn with_multiplier(cut_scores: impl Iterator<Item = u32>) -> u32 {
+    let mut multiplier = 1;
+    let mut progress = 0;
+    let mut total = 0;
+    for cut_score in cut_scores {
+        // the multiplier goes up as the note is cut, before it is scored
+        if multiplier < 8 {
+            progress += 1;
+            if progress >= multiplier * 2 {
+                multiplier *= 2;
+                progress = 0;
+            }
+        }
+        total += cut_score * multiplier;
+    }
+    total
+}
+
+/// The rank the game gives for a score, as an index into `SCORE_RANK_MAP`.
+pub fn rank_for_score(score: u32, max_score: u32) -> usize {
+    if max_score == 0 {
+        return 0;
+    }
+    if score >= max_score {
+        return SCORE_RANK_MAP.len() - 1;
+    }
+    let accuracy = score as f64 / max_score as f64;
+    [0.9, 0.8, 0.65, 0.5, 0.35, 0.2]
+        .iter()
+        .position(|threshold| accuracy > *threshold)
+        .map_or(0, |i| SCORE_RANK_MAP.len() - 2 - i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rank(score: u32, max_score: u32) -> &'static str {
+        SCORE_RANK_MAP[rank_for_score(score, max_score)]
+    }
+
+    #[test]
+    fn max_score_follows_multiplier_ramp() {
+        // x1 for the first note, x2 for the next 4, x4 for the next 8, then x8
+        assert_eq!(max_score_for_notes(0), 0);
+        assert_eq!(max_score_for_notes(1), 115);
+        assert_eq!(max_score_for_notes(5), 115 * (1 + 4 * 2));
+        assert_eq!(max_score_for_notes(13), 115 * (1 + 4 * 2 + 8 * 4));
+        for n in [14, 15, 100, 1000] {
+            assert_eq!(max_score_for_notes(n), 115 * (8 * n - 63));
+        }
+    }
+
+    #[test]
+    fn max_score_of_beatmap_matches_notes_only() {
+        let notes = (0..20)
+            .map(|b| json!({"b": b, "x": 1, "y": 0, "c": 0, "d": 1}))
+            .collect::<Vec<_>>();
+        let beatmap =
+            Beatmap::parse(&json!({"version": "3.0.0", "colorNotes": notes}), 120.0).unwrap();
+        assert_eq!(max_score(&beatmap), max_score_for_notes(20));
+    }
+
+    #[test]
+    fn chain_head_and_links() {
+        let chain =
+            json!({"b": 0, "x": 1, "y": 0, "c": 0, "d": 1, "tb": 1, "tx": 1, "ty": 2, "sc": 3});
+        // the color note under the chain's head scores 85, then two links at 20
+        let with_head = Beatmap::parse(
+            &json!({
+                "version": "3.0.0",
+                "colorNotes": [{"b": 0, "x": 1, "y": 0, "c": 0, "d": 1}],
+                "burstSliders": [chain],
+            }),
+            120.0,
+        )
+        .unwrap();
+        assert_eq!(with_head.chain_links, vec![0.5, 1.0]);
+        assert_eq!(max_score(&with_head), 85 + 20 * 2 + 20 * 2);
+        // a color note elsewhere is a normal note
+        let without_head = Beatmap::parse(
+            &json!({
+                "version": "3.0.0",
+                "colorNotes": [{"b": 0, "x": 2, "y": 0, "c": 0, "d": 1}],
+                "burstSliders": [chain],
+            }),
+            120.0,
+        )
+        .unwrap();
+        assert_eq!(max_score(&without_head), 115 + 20 * 2 + 20 * 2);
+    }
+
+    #[test]
+    fn rank_thresholds() {
+        assert_eq!(rank(1000, 1000), "SSS");
+        assert_eq!(rank(1100, 1000), "SSS");
+        assert_eq!(rank(999, 1000), "SS");
+        // each rank needs more than its threshold
+        for (threshold, above, at) in [
+            (900, "SS", "S"),
+            (800, "S", "A"),
+            (650, "A", "B"),
+            (500, "B", "C"),
+            (350, "C", "D"),
+            (200, "D", "E"),
+        ] {
+            assert_eq!(rank(threshold + 1, 1000), above, "{} + 1", threshold);
+            assert_eq!(rank(threshold, 1000), at, "{}", threshold);
+        }
+        assert_eq!(rank(0, 1000), "E");
+        assert_eq!(rank(0, 0), "E");
+    }
+}
@@ -0,0 +1,297 @@
+use crate::players::Player;
+use crate::summary::Summary;
+use crate::{sort, LevelInfo};
+use clap::ArgMatches;
+use log::{debug, warn};
+use serde::Serialize;
+use std::io::Cursor;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tiny_http::{Header, Method, Response, Server};
+
+/// The players and their levels, as computed by the most recent scan.
+struct Stats {
+    players: Vec<Player>,
+    level_stats: Vec<LevelInfo>,
+}
+
+/// One played difficulty in `/players/{n}/scores`.
+#[derive(Serialize)]
+struct Score<'a> {
+    id: &'a str,
+    song: &'a str,
+    artist: &'a str,
+    mapper: &'a str,
+    characteristic: &'a str,
+    difficulty: &'a str,
+    score: u32,
+    max_score: Option<u32>,
+    accuracy: Option<f64>,
+    combo: &'a str,
+    rank: &'a str,
+    plays: u32,
+    valid: bool,
+}
+
+/// Serves the stats from `load` as JSON on localhost, replacing them with a fresh `load` every
+/// `--rescan-interval` seconds, or keeping them if the rescan panics. Never returns.
+pub fn run(args: &ArgMatches, load: impl Fn() -> (Vec<Player>, Vec<LevelInfo>)) {
+    let port = args.value_of("port").unwrap().parse::<u16>().unwrap();
+    let interval = args
+        .value_of("rescan-interval")
+        .unwrap()
+        .parse::<u64>()
+        .unwrap();
+    if interval == 0 {
+        panic!("Rescan interval must be at least 1 second");
+    }
+
+    let (players, level_stats) = load();
+    let stats = Arc::new(RwLock::new(Stats {
+        players,
+        level_stats,
+    }));
+    let server = Server::http(("127.0.0.1", port))
+        .unwrap_or_else(|e| panic!("Can't listen on port {}: {}", port, e));
+    println!("Serving stats on http://127.0.0.1:{}", port);
+    {
+        let stats = Arc::clone(&stats);
+        std::thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let response = response(&stats.read().unwrap(), request.method(), request.url());
+                debug!(
+                    "{} {} -> {}",
+                    request.method(),
+                    request.url(),
+                    response.status_code().0
+                );
+                if let Err(e) = request.respond(response) {
+                    warn!("Couldn't send response: {}", e);
+                }
+            }
+        });
+    }
+
+    loop {
+        std::thread::sleep(Duration::from_secs(interval));
+        debug!("Progress: rescanning");
+        // keep serving the previous stats if a level or the save file can't be read mid-write
+        match panic::catch_unwind(AssertUnwindSafe(&load)) {
+            Ok((players, level_stats)) => {
+                *stats.write().unwrap() = Stats {
+                    players,
+                    level_stats,
+                }
+            }
+            Err(_) => warn!("Rescan failed, still serving the previous stats"),
+        }
+    }
+}
+
+/// The JSON response to a request.
+fn response(stats: &Stats, method: &Method, url: &str) -> Response<Cursor<Vec<u8>>> {
+    let (status, body) = if *method == Method::Get {
+        respond(stats, url)
+    } else {
+        (405, error("Only GET is supported"))
+    };
+    Response::from_string(body)
+        .with_status_code(status)
+        .with_header(Header::from_bytes("Content-Type", "application/json").unwrap())
+}
+
+fn json<T: Serialize + ?Sized>(value: &T) -> (u16, String) {
+    (200, serde_json::to_string(value).unwrap())
+}
+
+fn error(message: &str) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
+
+/// The status code and JSON body for a GET of `url`.
+fn respond(stats: &Stats, url: &str) -> (u16, String) {
+    let path = url.split('?').next().unwrap().trim_end_matches('/');
+    let segments = path.split('/').skip(1).collect::<Vec<_>>();
+    match segments.as_slice() {
+        ["levels"] => json(&stats.level_stats),
+        ["levels", id] => {
+            let levels = stats
+                .level_stats
+                .iter()
+                .filter(|l| l.id == *id)
+                .collect::<Vec<_>>();
+            if levels.is_empty() {
+                (404, error(&format!("No level with ID {}", id)))
+            } else {
+                json(&levels)
+            }
+        }
+        ["players"] => json(&stats.players),
+        ["players", n, "scores"] => {
+            match n.parse::<usize>().ok().and_then(|n| stats.players.get(n)) {
+                Some(player) => json(&scores(&stats.level_stats, player.index)),
+                None => (404, error(&format!("No player {}", n))),
+            }
+        }
+        ["summary"] => {
+            let summaries = stats
+                .players
+                .iter()
+                .map(|p| {
+                    let levels = stats
+                        .level_stats
+                        .iter()
+                        .filter(|l| l.player_index == p.index)
+                        .collect::<Vec<_>>();
+                    Summary::new(&p.name, &levels)
+                })
+                .collect::<Vec<_>>();
+            json(&summaries)
+        }
+        _ => (404, error(&format!("Unknown path {}", path))),
+    }
+}
+
+/// A player's played difficulties, in the same order as the stats CSV.
+fn scores(level_stats: &[LevelInfo], player_index: usize) -> Vec<Score<'_>> {
+    sort::rows(level_stats)
+        .into_iter()
+        .filter(|r| r.level.player_index == player_index && r.stats.plays > 0)
+        .map(|r| Score {
+            id: &r.level.id,
+            song: &r.level.song,
+            artist: &r.level.artist,
+            mapper: &r.level.mapper,
+            characteristic: r.characteristic,
+            difficulty: r.difficulty,
+            score: r.stats.score,
+            max_score: r.stats.max_score,
+            accuracy: r.stats.accuracy(),
+            combo: &r.stats.combo,
+            rank: &r.stats.rank,
+            plays: r.stats.plays,
+            valid: r.stats.valid,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{add_difficulty, difficulty, level};
+    use serde_json::Value;
+    use std::io::Read;
+
+    const PLAYERS: [&str; 3] = ["Alice", "Guest", "Guest"];
+
+    /// Two levels for each player, where player n has played n + 1 difficulties.
+    fn stats() -> Stats {
+        let mut levels = Vec::new();
+        for (index, player) in PLAYERS.iter().enumerate() {
+            let mut a = level("custom_level_AAAA", player);
+            a.player_index = index;
+            for (n, name) in ["Hard", "Expert", "Expert+"].iter().enumerate() {
+                let rank = if n <= index { "A" } else { "" };
+                add_difficulty(&mut a, name, difficulty(8.0, rank));
+            }
+            levels.push(a);
+            let mut b = level("custom_level_BBBB", player);
+            b.player_index = index;
+            levels.push(b);
+        }
+        Stats {
+            players: PLAYERS
+                .iter()
+                .enumerate()
+                .map(|(index, name)| Player {
+                    name: name.to_string(),
+                    index,
+                    player_name: name.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            level_stats: levels,
+        }
+    }
+
+    /// The status and parsed body of a request, checking that it's JSON.
+    fn get(method: Method, url: &str) -> (u16, Value) {
+        let response = response(&stats(), &method, url);
+        let status = response.status_code().0;
+        let content_type = response
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv("Content-Type"))
+            .map(|h| h.value.to_string());
+        assert_eq!(content_type.as_deref(), Some("application/json"));
+        let mut body = String::new();
+        response.into_reader().read_to_string(&mut body).unwrap();
+        (status, serde_json::from_str(&body).unwrap())
+    }
+
+    #[test]
+    fn levels() {
+        let (status, body) = get(Method::Get, "/levels");
+        assert_eq!(status, 200);
+        assert_eq!(body.as_array().unwrap().len(), 6);
+
+        let (status, body) = get(Method::Get, "/levels/custom_level_AAAA/?pretty");
+        assert_eq!(status, 200);
+        let levels = body.as_array().unwrap();
+        assert_eq!(levels.len(), 3);
+        assert_eq!(levels[0]["player"], "Alice");
+        assert_eq!(
+            levels[0]["characteristics"]["Standard"]["difficulties"]["Hard"]["rank"],
+            "A"
+        );
+    }
+
+    #[test]
+    fn players() {
+        let (status, body) = get(Method::Get, "/players");
+        assert_eq!(status, 200);
+        assert_eq!(body[1]["playerName"], "Guest");
+
+        let (status, body) = get(Method::Get, "/players/0/scores");
+        assert_eq!(status, 200);
+        let scores = body.as_array().unwrap();
+        assert_eq!(scores.len(), 1);
+        assert_eq!(scores[0]["id"], "custom_level_AAAA");
+        assert_eq!(scores[0]["difficulty"], "Hard");
+
+        // players with the same name are kept apart
+        for n in [1, 2] {
+            let (_, body) = get(Method::Get, &format!("/players/{}/scores", n));
+            assert_eq!(body.as_array().unwrap().len(), n + 1);
+        }
+    }
+
+    #[test]
+    fn summary() {
+        let (status, body) = get(Method::Get, "/summary");
+        assert_eq!(status, 200);
+        assert_eq!(body.as_array().unwrap().len(), 3);
+        assert_eq!(body[0]["player"], "Alice");
+        for n in [1, 2] {
+            assert_eq!(body[n]["player"], "Guest");
+            assert_eq!(body[n]["difficulties_played"], n + 1);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        for (method, url, status) in [
+            (Method::Get, "/levels/custom_level_CCCC", 404),
+            (Method::Get, "/players/3/scores", 404),
+            (Method::Get, "/players/x/scores", 404),
+            (Method::Get, "/", 404),
+            (Method::Get, "/stats", 404),
+            (Method::Post, "/levels", 405),
+        ] {
+            let (actual, body) = get(method, url);
+            assert_eq!(actual, status, "{}", url);
+            assert!(body["error"].is_string(), "{}", url);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use crate::filter::{self, Row, Value};
+use crate::{LevelInfo, DIFFICULTY_NAME_MAP};
+use std::cmp::Ordering;
+
+pub struct SortKey {
+    pub field: String,
+    pub descending: bool,
+}
+
+/// Parses comma-separated `--sort` keys like `valid:desc,score:desc`. Fields are the same as for
+/// `--filter`, ascending unless followed by `:desc`.
+pub fn parse_sort_keys(keys: &str, windows: &[f64]) -> Result<Vec<SortKey>, String> {
+    keys.split(',')
+        .map(|key| {
+            let (field, direction) = key.trim().split_once(':').unwrap_or((key.trim(), "asc"));
+            if filter::field_type(field, windows).is_none() {
+                return Err(filter::unknown_field(field));
+            }
+            let descending = match direction {
+                "asc" => false,
+                "desc" => true,
+                _ => return Err(format!("unknown direction {}, use asc or desc", direction)),
+            };
+            Ok(SortKey {
+                field: field.to_owned(),
+                descending,
+            })
+        })
+        .collect()
+}
+
+/// Every difficulty as a row, in the order of `level_stats`, then characteristic name, then
+/// difficulty in game order.
+pub fn rows(level_stats: &[LevelInfo]) -> Vec<Row<'_>> {
+    let mut rows = Vec::new();
+    for l in level_stats {
+        let mut characteristics = l.characteristics.iter().collect::<Vec<_>>();
+        characteristics.sort_by(|a, b| a.0.cmp(b.0));
+        for c in characteristics {
+            let mut difficulties = c.1.difficulties.iter().collect::<Vec<_>>();
+            difficulties.sort_by_key(|d| DIFFICULTY_NAME_MAP.iter().position(|n| n == d.0));
+            for d in difficulties {
+                rows.push(Row {
+                    level: l,
+                    characteristic: c.0,
+                    difficulty: d.0,
+                    stats: d.1,
+                });
+            }
+        }
+    }
+    rows
+}
+
+/// Stable sort by each key in turn. Missing values go last in either direction.
+pub fn sort_rows(rows: &mut [Row], keys: &[SortKey]) {
+    if keys.is_empty() {
+        return;
+    }
+    rows.sort_by_cached_key(|row| {
+        keys.iter()
+            .map(|k| SortValue(row.value(&k.field), k.descending))
+            .collect::<Vec<_>>()
+    });
+}
+
+struct SortValue(Value, bool);
+
+impl PartialEq for SortValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for SortValue {}
+
+impl PartialOrd for SortValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SortValue {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (&self.0, &other.0) {
+            (Value::Null, Value::Null) => Ordering::Equal,
+            (Value::Null, _) => Ordering::Greater,
+            (_, Value::Null) => Ordering::Less,
+            (a, b) => {
+                let ordering = a.partial_cmp(b).unwrap_or(Ordering::Equal);
+                if self.1 {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            }
+        }
+    }
+}
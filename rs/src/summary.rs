@@ -0,0 +1,181 @@
+use crate::density::round2;
+use crate::players::Player;
+use crate::{LevelInfo, DIFFICULTY_NAME_MAP, SCORE_RANK_MAP};
+use clap::ArgMatches;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Totals for one player over every output difficulty.
+#[derive(Serialize)]
+pub struct Summary {
+    pub player: String,
+    pub levels: u32,
+    pub levels_played: u32,
+    pub difficulties: u32,
+    pub difficulties_played: u32,
+    pub plays: u32,
+    pub full_combos: u32,
+    pub valid_scores: u32,
+    pub invalid_scores: u32,
+    /// Played difficulties per rank, in the order of `SCORE_RANK_MAP`.
+    pub ranks: Vec<RankCount>,
+    /// Per difficulty, in the order of `DIFFICULTY_NAME_MAP`.
+    pub by_difficulty: Vec<Progress>,
+    /// Per characteristic, sorted by name.
+    pub by_characteristic: Vec<Progress>,
+}
+
+#[derive(Serialize)]
+pub struct RankCount {
+    pub rank: String,
+    pub count: u32,
+}
+
+/// How many of a group of difficulties have been played.
+#[derive(Serialize, Default)]
+pub struct Progress {
+    pub name: String,
+    pub total: u32,
+    pub played: u32,
+    pub full_combos: u32,
+    pub completion: f64, // percent played
+}
+
+impl Progress {
+    fn add(&mut self, played: bool, full_combo: bool) {
+        self.total += 1;
+        self.played += played as u32;
+        self.full_combos += full_combo as u32;
+        self.completion = round2(self.played as f64 * 100.0 / self.total as f64);
+    }
+}
+
+impl Summary {
+    pub fn new(player: &str, level_stats: &[&LevelInfo]) -> Summary {
+        let mut summary = Summary {
+            player: player.to_owned(),
+            levels: level_stats.len() as u32,
+            levels_played: 0,
+            difficulties: 0,
+            difficulties_played: 0,
+            plays: 0,
+            full_combos: 0,
+            valid_scores: 0,
+            invalid_scores: 0,
+            ranks: SCORE_RANK_MAP
+                .iter()
+                .map(|r| RankCount {
+                    rank: r.to_string(),
+                    count: 0,
+                })
+                .collect(),
+            by_difficulty: DIFFICULTY_NAME_MAP
+                .iter()
+                .map(|d| Progress {
+                    name: d.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            by_characteristic: Vec::new(),
+        };
+        let mut by_characteristic = BTreeMap::<&str, Progress>::new();
+        for l in level_stats {
+            let mut level_played = false;
+            for c in &l.characteristics {
+                for d in &c.1.difficulties {
+                    let played = d.1.plays > 0;
+                    let full_combo = played && d.1.combo == "FC";
+                    level_played |= played;
+                    summary.difficulties += 1;
+                    if played {
+                        summary.difficulties_played += 1;
+                        summary.plays += d.1.plays;
+                        summary.full_combos += full_combo as u32;
+                        if d.1.valid {
+                            summary.valid_scores += 1;
+                        } else {
+                            summary.invalid_scores += 1;
+                        }
+                        if let Some(rank) = summary.ranks.iter_mut().find(|r| r.rank == d.1.rank) {
+                            rank.count += 1;
+                        }
+                    }
+                    if let Some(progress) =
+                        summary.by_difficulty.iter_mut().find(|p| &p.name == d.0)
+                    {
+                        progress.add(played, full_combo);
+                    }
+                    by_characteristic
+                        .entry(c.0)
+                        .or_insert_with(|| Progress {
+                            name: c.0.clone(),
+                            ..Default::default()
+                        })
+                        .add(played, full_combo);
+                }
+            }
+            summary.levels_played += level_played as u32;
+        }
+        summary.by_characteristic = by_characteristic.into_values().collect();
+        summary
+    }
+}
+
+/// Prints a summary per player instead of the stats for every difficulty.
+pub fn run(level_stats: &[LevelInfo], players: &[Player], args: &ArgMatches) {
+    let summaries = players
+        .iter()
+        .map(|p| {
+            let levels = level_stats
+                .iter()
+                .filter(|l| l.player_index == p.index)
+                .collect::<Vec<_>>();
+            Summary::new(&p.name, &levels)
+        })
+        .collect::<Vec<_>>();
+    if args.is_present("json") {
+        println!("{}", serde_json::to_string_pretty(&summaries).unwrap());
+        return;
+    }
+    for summary in &summaries {
+        println!("{}", summary.player);
+        println!(
+            "  Levels played: {} of {}",
+            summary.levels_played, summary.levels
+        );
+        println!(
+            "  Difficulties played: {} of {}, {} plays",
+            summary.difficulties_played, summary.difficulties, summary.plays
+        );
+        println!("  Full combos: {}", summary.full_combos);
+        println!(
+            "  Scores: {} valid, {} invalid",
+            summary.valid_scores, summary.invalid_scores
+        );
+        println!(
+            "  Ranks: {}",
+            summary
+                .ranks
+                .iter()
+                .rev()
+                .map(|r| format!("{} {}", r.rank, r.count))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        print_progress("Difficulty", &summary.by_difficulty);
+        print_progress("Characteristic", &summary.by_characteristic);
+    }
+}
+
+fn print_progress(title: &str, progress: &[Progress]) {
+    println!(
+        "  {:<16} {:>7} {:>7} {:>12} {:>7}",
+        title, "Played", "Total", "Completion %", "FCs"
+    );
+    for p in progress.iter().filter(|p| p.total > 0) {
+        println!(
+            "  {:<16} {:>7} {:>7} {:>12} {:>7}",
+            p.name, p.played, p.total, p.completion, p.full_combos
+        );
+    }
+}
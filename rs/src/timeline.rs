@@ -0,0 +1,134 @@
+use crate::beatmap::{Beatmap, NoteKind};
+use crate::sort;
+use crate::LevelInfo;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::path::Path;
+
+/// Object counts per fixed-length bucket of real time, starting at the beginning of the song.
+#[derive(Serialize, Clone)]
+pub struct Timeline {
+    pub bucket: f64, // seconds
+    /// Notes (not bombs) starting in each bucket.
+    pub notes: Vec<u32>,
+    pub bombs: Vec<u32>,
+    /// Walls present at any point during each bucket.
+    pub walls: Vec<u32>,
+}
+
+/// Parses `--timeline-bucket`, which has to be a positive number of seconds.
+pub fn parse_bucket(s: &str) -> f64 {
+    let bucket = s
+        .trim()
+        .parse::<f64>()
+        .unwrap_or_else(|_| panic!("Timeline bucket must be a number, got {}", s));
+    if !bucket.is_finite() || bucket <= 0.0 {
+        panic!("Timeline bucket must be positive, got {}", s);
+    }
+    bucket
+}
+
+impl Timeline {
+    pub fn new(beatmap: &Beatmap, bucket: f64) -> Timeline {
+        let tempo_map = &beatmap.tempo_map;
+        let bucket_idx = |seconds: f64| (seconds.max(0.0) / bucket).floor() as usize;
+        let last_seconds = beatmap
+            .notes
+            .iter()
+            .map(|n| tempo_map.seconds(n.beat))
+            .chain(
+                beatmap
+                    .obstacles
+                    .iter()
+                    .map(|o| tempo_map.seconds(o.beat + o.duration.max(0.0))),
+            )
+            .fold(0.0, f64::max);
+        let len = bucket_idx(last_seconds) + 1;
+        let mut timeline = Timeline {
+            bucket,
+            notes: vec![0; len],
+            bombs: vec![0; len],
+            walls: vec![0; len],
+        };
+        for note in &beatmap.notes {
+            let idx = bucket_idx(tempo_map.seconds(note.beat));
+            if note.kind == NoteKind::Bomb {
+                timeline.bombs[idx] += 1;
+            } else {
+                timeline.notes[idx] += 1;
+            }
+        }
+        for obstacle in &beatmap.obstacles {
+            let start_idx = bucket_idx(tempo_map.seconds(obstacle.beat));
+            let end_idx = bucket_idx(tempo_map.seconds(obstacle.beat + obstacle.duration.max(0.0)));
+            for count in &mut timeline.walls[start_idx..=end_idx] {
+                *count += 1;
+            }
+        }
+        timeline
+    }
+}
+
+/// Writes every computed timeline to `path`, as JSON if the extension is `.json` and CSV otherwise.
+pub fn write(level_stats: &[LevelInfo], path: &Path) {
+    if path.exists() {
+        fs::remove_file(path).unwrap();
+    }
+    if path.extension().is_some_and(|e| e == "json") {
+        // { level id: { characteristic: { difficulty: timeline } } }
+        let mut levels = Map::new();
+        for l in level_stats {
+            let mut chars = Map::new();
+            for c in &l.characteristics {
+                let diffs =
+                    c.1.difficulties
+                        .iter()
+                        .filter_map(|d| Some((d.0.clone(), json!(d.1.timeline.as_ref()?))))
+                        .collect::<Map<_, _>>();
+                if !diffs.is_empty() {
+                    chars.insert(c.0.clone(), Value::Object(diffs));
+                }
+            }
+            if !chars.is_empty() {
+                levels.insert(l.id.clone(), Value::Object(chars));
+            }
+        }
+        serde_json::to_writer(fs::File::create(path).unwrap(), &levels).unwrap();
+    } else {
+        let mut wtr = csv::Writer::from_path(path).unwrap();
+        wtr.write_record([
+            "ID",
+            "Song",
+            "Characteristic",
+            "Difficulty",
+            "Start",
+            "Notes",
+            "NPS",
+            "Bombs",
+            "Walls",
+        ])
+        .unwrap();
+        for row in sort::rows(level_stats) {
+            let timeline = match &row.stats.timeline {
+                Some(t) => t,
+                None => continue,
+            };
+            for i in 0..timeline.notes.len() {
+                wtr.write_record([
+                    &row.level.id,
+                    &row.level.song,
+                    row.characteristic,
+                    row.difficulty,
+                    &(i as f64 * timeline.bucket).to_string(),
+                    &timeline.notes[i].to_string(),
+                    &(timeline.notes[i] as f64 / timeline.bucket).to_string(),
+                    &timeline.bombs[i].to_string(),
+                    &timeline.walls[i].to_string(),
+                ])
+                .unwrap();
+            }
+        }
+        wtr.flush().unwrap();
+    }
+}
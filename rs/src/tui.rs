@@ -0,0 +1,612 @@
+use crate::density::round2;
+use crate::filter::{Row, Value};
+use crate::sort::{self, SortKey};
+use crate::{LevelInfo, DIFFICULTY_NAME_MAP, SCORE_RANK_MAP};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{self, Block, Paragraph, Table, TableState};
+use ratatui::Frame;
+use std::collections::HashSet;
+
+// the metrics levels can be sorted by, followed by one NP{n}S per window
+const SORT_FIELDS: [&str; 14] = [
+    "song",
+    "artist",
+    "mapper",
+    "bpm",
+    "duration",
+    "difficulty",
+    "notes",
+    "nps",
+    "tech",
+    "speed",
+    "score",
+    "accuracy",
+    "rank",
+    "plays",
+];
+
+const HELP: &str = "↑↓ move  Enter open  Esc back  / search  s/S sort field  r reverse  q quit";
+
+#[derive(Clone, Copy, PartialEq)]
+enum View {
+    Levels,
+    Characteristics,
+    Difficulties,
+    Difficulty,
+}
+
+struct App<'a> {
+    level_stats: &'a [LevelInfo],
+    fields: Vec<String>,
+    sort_field: usize,
+    descending: bool,
+    search: String,
+    searching: bool,
+    /// The levels matching the search, sorted, with the sort value of their first difficulty in
+    /// that order.
+    levels: Vec<(&'a LevelInfo, Value)>,
+    view: View,
+    level_state: TableState,
+    characteristic_state: TableState,
+    difficulty_state: TableState,
+}
+
+/// Browses the levels in the terminal until `q` is pressed: levels, then a level's
+/// characteristics, then their difficulties, then one difficulty's stats and density timeline.
+pub fn run(level_stats: &[LevelInfo], windows: &[f64]) {
+    let mut fields = SORT_FIELDS.map(|f| f.to_owned()).to_vec();
+    fields.extend(windows.iter().map(|w| format!("np{}s", w)));
+    let mut app = App {
+        level_stats,
+        fields,
+        sort_field: 0,
+        descending: false,
+        search: String::new(),
+        searching: false,
+        levels: Vec::new(),
+        view: View::Levels,
+        level_state: TableState::default(),
+        characteristic_state: TableState::default(),
+        difficulty_state: TableState::default(),
+    };
+    app.refresh();
+
+    let mut terminal = ratatui::init();
+    loop {
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+        match event::read().unwrap() {
+            Event::Key(key) if key.kind == KeyEventKind::Press => {
+                if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                    break;
+                }
+                if !app.handle_key(key.code) {
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    ratatui::restore();
+}
+
+impl<'a> App<'a> {
+    /// Re-sorts and re-searches the levels, selecting the first.
+    fn refresh(&mut self) {
+        let search = self.search.to_lowercase();
+        let matches = |l: &LevelInfo| {
+            search.is_empty()
+                || [&l.song, &l.artist, &l.mapper]
+                    .iter()
+                    .any(|s| s.to_lowercase().contains(&search))
+        };
+        let field = &self.fields[self.sort_field];
+        let mut rows = sort::rows(self.level_stats);
+        rows.retain(|r| matches(r.level));
+        sort::sort_rows(
+            &mut rows,
+            &[SortKey {
+                field: field.clone(),
+                descending: self.descending,
+            }],
+        );
+        // each level goes where its first difficulty is, e.g. its hardest when sorting by NPS
+        // descending
+        let mut levels = Vec::<(&LevelInfo, Value)>::new();
+        let mut seen = HashSet::<*const LevelInfo>::new();
+        for row in &rows {
+            if seen.insert(row.level) {
+                levels.push((row.level, row.value(field)));
+            }
+        }
+        // levels without difficulties have no rows
+        for l in self.level_stats {
+            if l.characteristics.is_empty() && matches(l) {
+                levels.push((l, Value::Null));
+            }
+        }
+        self.levels = levels;
+        self.level_state.select(if self.levels.is_empty() {
+            None
+        } else {
+            Some(0)
+        });
+    }
+
+    fn level(&self) -> Option<&'a LevelInfo> {
+        self.level_state.selected().map(|i| self.levels[i].0)
+    }
+
+    /// The selected level's characteristics, sorted by name.
+    fn characteristics(&self) -> Vec<&'a str> {
+        let mut characteristics: Vec<&str> = self
+            .level()
+            .map(|l| l.characteristics.keys().map(|c| c.as_str()).collect())
+            .unwrap_or_default();
+        characteristics.sort();
+        characteristics
+    }
+
+    fn characteristic(&self) -> Option<&'a str> {
+        self.characteristic_state
+            .selected()
+            .and_then(|i| self.characteristics().get(i).copied())
+    }
+
+    /// The rows of the selected characteristic, in game order.
+    fn difficulties(&self) -> Vec<Row<'a>> {
+        match (self.level(), self.characteristic()) {
+            (Some(level), Some(characteristic)) => sort::rows(std::slice::from_ref(level))
+                .into_iter()
+                .filter(|r| r.characteristic == characteristic)
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn state(&mut self) -> (&mut TableState, usize) {
+        match self.view {
+            View::Levels => (&mut self.level_state, self.levels.len()),
+            View::Characteristics => {
+                let len = self.characteristics().len();
+                (&mut self.characteristic_state, len)
+            }
+            View::Difficulties | View::Difficulty => {
+                let len = self.difficulties().len();
+                (&mut self.difficulty_state, len)
+            }
+        }
+    }
+
+    /// Moves the selection by `offset` rows, staying within the list.
+    fn scroll(&mut self, offset: isize) {
+        let (state, len) = self.state();
+        if len > 0 {
+            let selected = state.selected().unwrap_or(0) as isize + offset;
+            state.select(Some(selected.clamp(0, len as isize - 1) as usize));
+        }
+    }
+
+    /// Handles one key press. Returns false to quit.
+    fn handle_key(&mut self, code: KeyCode) -> bool {
+        if self.searching {
+            match code {
+                KeyCode::Char(c) => self.search.push(c),
+                KeyCode::Backspace => {
+                    self.search.pop();
+                }
+                KeyCode::Esc => {
+                    self.search.clear();
+                    self.searching = false;
+                }
+                KeyCode::Enter => self.searching = false,
+                _ => return true,
+            }
+            self.refresh();
+            return true;
+        }
+        match code {
+            KeyCode::Char('q') => return false,
+            KeyCode::Up | KeyCode::Char('k') => self.scroll(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.scroll(1),
+            KeyCode::PageUp => self.scroll(-20),
+            KeyCode::PageDown => self.scroll(20),
+            KeyCode::Home | KeyCode::Char('g') => self.scroll(isize::MIN / 2),
+            KeyCode::End | KeyCode::Char('G') => self.scroll(isize::MAX / 2),
+            KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => self.open(),
+            KeyCode::Esc | KeyCode::Left | KeyCode::Char('h') | KeyCode::Backspace => self.back(),
+            KeyCode::Char('/') if self.view == View::Levels => self.searching = true,
+            KeyCode::Char('s') if self.view == View::Levels => {
+                self.sort_field = (self.sort_field + 1) % self.fields.len();
+                self.refresh();
+            }
+            KeyCode::Char('S') if self.view == View::Levels => {
+                self.sort_field = (self.sort_field + self.fields.len() - 1) % self.fields.len();
+                self.refresh();
+            }
+            KeyCode::Char('r') if self.view == View::Levels => {
+                self.descending = !self.descending;
+                self.refresh();
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn open(&mut self) {
+        match self.view {
+            View::Levels if self.level().is_some() => {
+                self.view = View::Characteristics;
+                let first = (!self.characteristics().is_empty()).then_some(0);
+                self.characteristic_state.select(first);
+            }
+            View::Characteristics if self.characteristic().is_some() => {
+                self.view = View::Difficulties;
+                let first = (!self.difficulties().is_empty()).then_some(0);
+                self.difficulty_state.select(first);
+            }
+            View::Difficulties if self.difficulty_state.selected().is_some() => {
+                self.view = View::Difficulty
+            }
+            _ => {}
+        }
+    }
+
+    fn back(&mut self) {
+        self.view = match self.view {
+            View::Levels => {
+                if !self.search.is_empty() {
+                    self.search.clear();
+                    self.refresh();
+                }
+                View::Levels
+            }
+            View::Characteristics => View::Levels,
+            View::Difficulties => View::Characteristics,
+            View::Difficulty => View::Difficulties,
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [title_area, main_area, status_area] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let mut title = vec!["Levels".to_owned()];
+        if self.view != View::Levels {
+            if let Some(level) = self.level() {
+                title.push(format!("{} - {}", level.song, level.artist));
+            }
+        }
+        if matches!(self.view, View::Difficulties | View::Difficulty) {
+            title.extend(self.characteristic().map(|c| c.to_owned()));
+        }
+        if self.view == View::Difficulty {
+            if let Some(row) = self.selected_difficulty() {
+                title.push(row.difficulty.to_owned());
+            }
+        }
+        frame.render_widget(Line::from(title.join(" > ")).bold(), title_area);
+
+        let status = if self.searching {
+            format!("Search: {}_", self.search)
+        } else {
+            format!(
+                "Sort: {} {}{}  |  {}",
+                self.fields[self.sort_field],
+                if self.descending { "desc" } else { "asc" },
+                if self.search.is_empty() {
+                    String::new()
+                } else {
+                    format!("  Search: {}", self.search)
+                },
+                HELP
+            )
+        };
+        frame.render_widget(Line::from(status).reversed(), status_area);
+
+        match self.view {
+            View::Levels => self.draw_levels(frame, main_area),
+            View::Characteristics => self.draw_characteristics(frame, main_area),
+            View::Difficulties => self.draw_difficulties(frame, main_area),
+            View::Difficulty => self.draw_difficulty(frame, main_area),
+        }
+    }
+
+    fn draw_levels(&mut self, frame: &mut Frame, area: Rect) {
+        let field = &self.fields[self.sort_field];
+        let all_players = self
+            .level_stats
+            .iter()
+            .any(|l| l.player_index != self.level_stats[0].player_index);
+        let mut header = vec!["Song", "Artist", "Mapper"];
+        if all_players {
+            header.push("Player");
+        }
+        header.extend(["Difficulties", "Played", field]);
+        let rows = self.levels.iter().map(|(l, value)| {
+            let difficulties = l
+                .characteristics
+                .values()
+                .flat_map(|c| c.difficulties.values())
+                .collect::<Vec<_>>();
+            // levels that only have scores have no song name
+            let song = if l.song.is_empty() { &l.id } else { &l.song };
+            let mut cells = vec![song.clone(), l.artist.clone(), l.mapper.clone()];
+            if all_players {
+                cells.push(l.player.clone());
+            }
+            cells.extend([
+                difficulties.len().to_string(),
+                difficulties
+                    .iter()
+                    .filter(|d| d.plays > 0)
+                    .count()
+                    .to_string(),
+                display(field, value),
+            ]);
+            widgets::Row::new(cells)
+        });
+        let mut widths = vec![
+            Constraint::Fill(3),
+            Constraint::Fill(2),
+            Constraint::Fill(2),
+        ];
+        if all_players {
+            widths.push(Constraint::Fill(1));
+        }
+        widths.extend([
+            Constraint::Length(12),
+            Constraint::Length(6),
+            Constraint::Length(12),
+        ]);
+        let table = Table::new(rows, widths)
+            .header(widgets::Row::new(header).bold())
+            .block(Block::bordered().title(format!("{} levels", self.levels.len())))
+            .row_highlight_style(Style::new().reversed());
+        frame.render_stateful_widget(table, area, &mut self.level_state);
+    }
+
+    fn draw_characteristics(&mut self, frame: &mut Frame, area: Rect) {
+        let level = self.level().unwrap();
+        let rows = self.characteristics().into_iter().map(|c| {
+            let difficulties = &level.characteristics[c].difficulties;
+            let mut names = difficulties.keys().map(|d| d.as_str()).collect::<Vec<_>>();
+            names.sort_by_key(|d| DIFFICULTY_NAME_MAP.iter().position(|n| n == d));
+            widgets::Row::new([
+                c.to_owned(),
+                names.join(", "),
+                difficulties
+                    .values()
+                    .filter(|d| d.plays > 0)
+                    .count()
+                    .to_string(),
+            ])
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(20),
+                Constraint::Fill(1),
+                Constraint::Length(6),
+            ],
+        )
+        .header(widgets::Row::new(["Characteristic", "Difficulties", "Played"]).bold())
+        .block(Block::bordered().title(format!("{} ({})", level.song, level.mapper)))
+        .row_highlight_style(Style::new().reversed());
+        frame.render_stateful_widget(table, area, &mut self.characteristic_state);
+    }
+
+    fn draw_difficulties(&mut self, frame: &mut Frame, area: Rect) {
+        // the first peak density window, NP10S by default
+        let np10s = self
+            .fields
+            .iter()
+            .find(|f| f.starts_with("np") && f.ends_with('s') && f.as_str() != "nps")
+            .cloned()
+            .unwrap_or_else(|| "nps".to_owned());
+        let columns = [
+            "difficulty",
+            "label",
+            "notes",
+            "nps",
+            &np10s,
+            "score",
+            "accuracy",
+            "rank",
+            "combo",
+            "plays",
+        ];
+        let rows = self.difficulties().into_iter().map(|r| {
+            widgets::Row::new(
+                columns
+                    .iter()
+                    .map(|c| display(c, &r.value(c)))
+                    .collect::<Vec<_>>(),
+            )
+        });
+        let np10s_header = np10s.to_uppercase();
+        let header = [
+            "Difficulty",
+            "Label",
+            "Notes",
+            "NPS",
+            &np10s_header,
+            "Score",
+            "Accuracy %",
+            "Rank",
+            "Combo",
+            "Plays",
+        ];
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(10),
+                Constraint::Fill(1),
+                Constraint::Length(6),
+                Constraint::Length(6),
+                Constraint::Length(6),
+                Constraint::Length(8),
+                Constraint::Length(10),
+                Constraint::Length(5),
+                Constraint::Length(6),
+                Constraint::Length(6),
+            ],
+        )
+        .header(widgets::Row::new(header).bold())
+        .block(Block::bordered().title(self.characteristic().unwrap_or_default()))
+        .row_highlight_style(Style::new().reversed());
+        frame.render_stateful_widget(table, area, &mut self.difficulty_state);
+    }
+
+    fn selected_difficulty(&self) -> Option<Row<'a>> {
+        self.difficulty_state
+            .selected()
+            .and_then(|i| self.difficulties().get(i).copied())
+    }
+
+    fn draw_difficulty(&mut self, frame: &mut Frame, area: Rect) {
+        let row = match self.selected_difficulty() {
+            Some(row) => row,
+            None => return,
+        };
+        let l = row.level;
+        let d = row.stats;
+        let mut lines = vec![
+            format!("{} - {} ({})", l.song, l.artist, l.mapper),
+            format!(
+                "{} {}{}",
+                row.characteristic,
+                row.difficulty,
+                d.label
+                    .as_ref()
+                    .map(|label| format!(" \"{}\"", label))
+                    .unwrap_or_default()
+            ),
+            format!(
+                "Notes: {}  NPS: {}  {}",
+                display("notes", &row.value("notes")),
+                display("nps", &row.value("nps")),
+                d.peak_nps
+                    .iter()
+                    .map(|p| format!("NP{}S: {}", p.window, p.nps))
+                    .collect::<Vec<_>>()
+                    .join("  ")
+            ),
+            format!(
+                "Tech: {}  Speed: {}",
+                display("tech", &row.value("tech")),
+                display("speed", &row.value("speed"))
+            ),
+        ];
+        if !d.requirements.is_empty() || !d.suggestions.is_empty() {
+            lines.push(format!(
+                "Requirements: {}  Suggestions: {}",
+                d.requirements.join(", "),
+                d.suggestions.join(", ")
+            ));
+        }
+        if d.plays > 0 {
+            lines.push(format!(
+                "Score: {}{}  Rank: {}  Combo: {}  Plays: {}{}",
+                d.score,
+                d.accuracy()
+                    .map(|a| format!(" ({}%)", a))
+                    .unwrap_or_default(),
+                d.rank,
+                d.combo,
+                d.plays,
+                if d.valid { "" } else { "  (invalid)" }
+            ));
+        } else {
+            lines.push("Not played".to_owned());
+        }
+        lines.push(String::new());
+        let inner_width = area.width.saturating_sub(2) as usize;
+        let chart_height = (area.height as usize).saturating_sub(lines.len() + 5);
+        match &d.timeline {
+            Some(timeline) if chart_height >= 3 => {
+                lines.push(format!("Notes per second, {}s buckets:", timeline.bucket));
+                let nps = timeline
+                    .notes
+                    .iter()
+                    .map(|&n| n as f64 / timeline.bucket)
+                    .collect::<Vec<_>>();
+                lines.extend(ascii_chart(
+                    &nps,
+                    timeline.bucket,
+                    inner_width,
+                    chart_height,
+                ));
+            }
+            Some(_) => lines.push("Not enough room for the timeline".to_owned()),
+            None => lines.push("No timeline, the level has no beatmap data".to_owned()),
+        }
+        let paragraph = Paragraph::new(lines.into_iter().map(Line::from).collect::<Vec<_>>())
+            .block(Block::bordered());
+        frame.render_widget(paragraph, area);
+    }
+}
+
+/// A field value as text, with ranks and difficulties by name.
+fn display(field: &str, value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) if field == "rank" => SCORE_RANK_MAP[*n as usize].to_owned(),
+        Value::Number(n) if field == "difficulty" => DIFFICULTY_NAME_MAP[*n as usize].to_owned(),
+        Value::Number(n) if field == "duration" => {
+            format!("{:02}:{:02}", (n / 60.0).floor(), (n % 60.0).floor())
+        }
+        Value::Number(n) => round2(*n).to_string(),
+        Value::Text(t) => t.clone(),
+    }
+}
+
+/// A bar chart of `values`, one per `bucket` seconds, `height` lines of `#` plus a time axis, at
+/// most `width` characters wide. Buckets are merged by their maximum when they don't fit.
+fn ascii_chart(values: &[f64], bucket: f64, width: usize, height: usize) -> Vec<String> {
+    const LABEL_WIDTH: usize = 7; // "  12.5 |"
+    let columns = width.saturating_sub(LABEL_WIDTH + 1).max(1);
+    let per_column = values.len().div_ceil(columns).max(1);
+    let merged = values
+        .chunks(per_column)
+        .map(|c| c.iter().cloned().fold(0.0, f64::max))
+        .collect::<Vec<_>>();
+    let max = merged.iter().cloned().fold(0.0, f64::max);
+    let mut lines = Vec::with_capacity(height + 2);
+    for line in 0..height {
+        // the value a bar has to reach to fill this line
+        let threshold = max * (height - line) as f64 / height as f64;
+        let label = if line == 0 {
+            format!("{:>6.1}", max)
+        } else {
+            " ".repeat(6)
+        };
+        let bars = merged
+            .iter()
+            .map(|&v| {
+                if max > 0.0 && v >= threshold {
+                    '#'
+                } else {
+                    ' '
+                }
+            })
+            .collect::<String>();
+        lines.push(format!("{} |{}", label, bars));
+    }
+    lines.push(format!("{:>6.1} +{}", 0.0, "-".repeat(merged.len())));
+    let end = format!("{}s", (values.len() as f64 * bucket).round());
+    lines.push(format!(
+        "{}0s{}{}",
+        " ".repeat(LABEL_WIDTH + 1),
+        " ".repeat(merged.len().saturating_sub(2 + end.len())),
+        end
+    ));
+    lines
+}
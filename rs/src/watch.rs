@@ -0,0 +1,217 @@
+use crate::{process_single_song, AnalysisOptions, LevelInfo};
+use log::{debug, warn};
+use notify::{RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// how long the files have to stay unchanged before rescanning, so a level being downloaded or the
+// save being written is only processed once it's complete
+const QUIET_PERIOD: Duration = Duration::from_secs(2);
+
+/// What changed since the last rescan.
+#[derive(Default)]
+struct Changes {
+    /// CustomLevels folder names, including removed folders.
+    folders: BTreeSet<String>,
+    save: bool,
+}
+
+/// Calls `update` with the scanned levels, then again each time a level folder in
+/// `custom_levels_path` or the save file changes, after reprocessing only the changed folders.
+/// Never returns.
+pub fn run(
+    custom_levels_path: &Path,
+    save_path: &Path,
+    mut scanned_levels: Vec<LevelInfo>,
+    analysis_options: &AnalysisOptions,
+    update: impl Fn(&[LevelInfo]),
+) {
+    update(&scanned_levels);
+
+    // events have absolute paths, so compare them with absolute paths
+    let custom_levels_path = &canonicalize(custom_levels_path);
+    // the game may replace the save rather than write to it, so watch the folder it's in
+    let save_folder = match save_path.parent() {
+        Some(folder) if !folder.as_os_str().is_empty() => canonicalize(folder),
+        _ => canonicalize(Path::new(".")),
+    };
+    let save_path = &save_folder.join(save_path.file_name().unwrap());
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).unwrap();
+    watcher
+        .watch(custom_levels_path, RecursiveMode::Recursive)
+        .unwrap_or_else(|e| panic!("Can't watch {}: {}", custom_levels_path.display(), e));
+    watcher
+        .watch(&save_folder, RecursiveMode::NonRecursive)
+        .unwrap_or_else(|e| panic!("Can't watch {}: {}", save_folder.display(), e));
+    println!(
+        "Watching {} and {} for changes",
+        custom_levels_path.display(),
+        save_path.display()
+    );
+
+    loop {
+        let mut changes = Changes::default();
+        let mut event = rx.recv().unwrap();
+        loop {
+            match event {
+                Ok(event) => {
+                    for path in &event.paths {
+                        changes.add(path, custom_levels_path, save_path);
+                    }
+                }
+                Err(e) => warn!("Error watching for changes: {}", e),
+            }
+            event = match rx.recv_timeout(QUIET_PERIOD) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => panic!("Stopped watching for changes"),
+            };
+        }
+        if changes.folders.is_empty() && !changes.save {
+            continue;
+        }
+
+        for folder in &changes.folders {
+            rescan_folder(
+                &mut scanned_levels,
+                custom_levels_path,
+                folder,
+                analysis_options,
+            );
+        }
+        if changes.save {
+            debug!("Progress: save changed, reloading scores");
+        }
+        update(&scanned_levels);
+        println!(
+            "Updated the stats: {} level folders changed{}",
+            changes.folders.len(),
+            if changes.save { ", save changed" } else { "" }
+        );
+    }
+}
+
+fn canonicalize(path: &Path) -> PathBuf {
+    path.canonicalize()
+        .unwrap_or_else(|e| panic!("Can't watch {}: {}", path.display(), e))
+}
+
+impl Changes {
+    /// Records a changed `path`. It has to be relative to the same folder as `custom_levels_path`
+    /// and `save_path`, or all of them absolute.
+    fn add(&mut self, path: &Path, custom_levels_path: &Path, save_path: &Path) {
+        if let Ok(relative) = path.strip_prefix(custom_levels_path) {
+            if let Some(Component::Normal(folder)) = relative.components().next() {
+                self.folders.insert(folder.to_string_lossy().into_owned());
+            }
+        } else if path == save_path {
+            self.save = true;
+        }
+    }
+}
+
+/// Replaces the levels from `folder` with a fresh scan of it, or removes them if it's gone. A level
+/// that can't be processed, e.g. because it's still being copied, is left out until it changes
+/// again.
+fn rescan_folder(
+    scanned_levels: &mut Vec<LevelInfo>,
+    custom_levels_path: &Path,
+    folder: &str,
+    analysis_options: &AnalysisOptions,
+) {
+    scanned_levels.retain(|l| l.folder.as_deref() != Some(folder));
+    let level_info_file = custom_levels_path.join(folder).join("info.dat");
+    if !level_info_file.is_file() {
+        debug!("Progress: removed folder={}", folder);
+        return;
+    }
+    debug!("Progress: rescanning folder={}", folder);
+    let mut processed = Arc::new(Mutex::new(Vec::new()));
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        process_single_song(level_info_file, &mut processed, analysis_options)
+    }));
+    match result {
+        Ok(()) => scanned_levels.append(&mut processed.lock().unwrap()),
+        Err(_) => warn!(
+            "Skipping {} until it changes again, it couldn't be processed",
+            folder
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changes(paths: &[&str], custom_levels_path: &str, save_path: &str) -> Changes {
+        let mut changes = Changes::default();
+        for path in paths {
+            changes.add(
+                Path::new(path),
+                Path::new(custom_levels_path),
+                Path::new(save_path),
+            );
+        }
+        changes
+    }
+
+    #[test]
+    fn absolute_paths() {
+        let changes = changes(
+            &[
+                "/game/Beat Saber_Data/CustomLevels/1a2b (Song A - Mapper)/info.dat",
+                "/game/Beat Saber_Data/CustomLevels/1a2b (Song A - Mapper)/Expert.dat",
+                "/game/Beat Saber_Data/CustomLevels/3c4d (Song B - Mapper)",
+                "/game/Beat Saber_Data/CustomLevels",
+                "/save/other.dat",
+            ],
+            "/game/Beat Saber_Data/CustomLevels",
+            "/save/PlayerData.dat",
+        );
+        assert_eq!(
+            changes.folders.into_iter().collect::<Vec<_>>(),
+            ["1a2b (Song A - Mapper)", "3c4d (Song B - Mapper)"]
+        );
+        assert!(!changes.save);
+    }
+
+    #[test]
+    fn save_changed() {
+        let changes = changes(
+            &["/save/PlayerData.dat"],
+            "/game/Beat Saber_Data/CustomLevels",
+            "/save/PlayerData.dat",
+        );
+        assert!(changes.folders.is_empty());
+        assert!(changes.save);
+    }
+
+    #[test]
+    fn relative_paths() {
+        let changes = changes(
+            &["game/CustomLevels/1a2b/info.dat", "PlayerData.dat"],
+            "game/CustomLevels",
+            "PlayerData.dat",
+        );
+        assert_eq!(changes.folders.into_iter().collect::<Vec<_>>(), ["1a2b"]);
+        assert!(changes.save);
+    }
+
+    #[test]
+    fn relative_paths_only_match_relative_paths() {
+        // why `run` canonicalizes the paths before watching
+        let changes = changes(
+            &["/root/game/CustomLevels/1a2b/info.dat"],
+            "game/CustomLevels",
+            "PlayerData.dat",
+        );
+        assert!(changes.folders.is_empty());
+        assert!(!changes.save);
+    }
+}